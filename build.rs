@@ -0,0 +1,47 @@
+fn main() {
+    #[cfg(feature = "capi")]
+    capi::build();
+}
+
+#[cfg(feature = "capi")]
+mod capi {
+    use std::env;
+    use std::path::PathBuf;
+
+    /// Generates the C header of the crate and compiles the C test program
+    /// against it, see `capi/test.c`.
+    ///
+    /// The `capi` test checks that the generated header matches the one in
+    /// `include/`, which is the one C programs should use.
+    pub fn build() {
+        let crate_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+        let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+        println!("cargo:rerun-if-changed=build.rs");
+        println!("cargo:rerun-if-changed=src/capi.rs");
+        println!("cargo:rerun-if-changed=capi/test.c");
+
+        let mut config = cbindgen::Config::default();
+        config.language = cbindgen::Language::C;
+        config.include_guard = Some("RECYCLING_TREE_H".to_owned());
+        config.usize_is_size_t = true;
+        config.autogen_warning =
+            Some("/* Generated from src/capi.rs by cbindgen, do not edit. */".to_owned());
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(crate_dir.join("src/capi.rs"))
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file(out_dir.join("recycling_tree.h"));
+
+        // The test program is linked into every test binary, not into the
+        // library itself, but only the `capi` test calls it.
+        let objects = cc::Build::new()
+            .file(crate_dir.join("capi/test.c"))
+            .include(&out_dir)
+            .warnings_into_errors(true)
+            .compile_intermediates();
+        for object in objects {
+            println!("cargo:rustc-link-arg-tests={}", object.display());
+        }
+    }
+}
@@ -0,0 +1,105 @@
+#include <stdio.h>
+#include <string.h>
+
+#include "recycling_tree.h"
+
+#define CHECK(cond)                                                       \
+    do {                                                                  \
+        if (!(cond)) {                                                    \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,        \
+                    __LINE__, #cond);                                     \
+            return 1;                                                     \
+        }                                                                 \
+    } while (0)
+
+static size_t new_count;
+static size_t drop_count;
+
+static void log_new(const RecyclingTreeNode *node) {
+    (void)node;
+    new_count++;
+}
+
+static void log_drop(const RecyclingTreeNode *node) {
+    (void)node;
+    drop_count++;
+}
+
+static int bytes_eq(const uint8_t *bytes, size_t len, const char *expected) {
+    return len == strlen(expected) && memcmp(bytes, expected, len) == 0;
+}
+
+static const RecyclingTreeNode *ensure_child(const RecyclingTreeNode *node,
+                                             const char *key,
+                                             const char *value) {
+    return recycling_tree_node_ensure_child(node, (const uint8_t *)key,
+                                            strlen(key), (const uint8_t *)value,
+                                            strlen(value));
+}
+
+int recycling_tree_capi_test(void) {
+    size_t len;
+    const uint8_t *bytes;
+    RecyclingTreeGcReport report;
+
+    recycling_tree_set_logger(log_new, log_drop);
+
+    RecyclingTree *tree = recycling_tree_new((const uint8_t *)"root", 4);
+    CHECK(new_count == 1);
+
+    const RecyclingTreeNode *root = recycling_tree_root(tree);
+    bytes = recycling_tree_node_value(root, &len);
+    CHECK(bytes_eq(bytes, len, "root"));
+    recycling_tree_node_key(root, &len);
+    CHECK(len == 0);
+    CHECK(recycling_tree_node_parent(root) == NULL);
+
+    const RecyclingTreeNode *a = ensure_child(root, "a", "first");
+    const RecyclingTreeNode *b = ensure_child(a, "b", "second");
+    CHECK(new_count == 3);
+    bytes = recycling_tree_node_key(b, &len);
+    CHECK(bytes_eq(bytes, len, "b"));
+    bytes = recycling_tree_node_value(b, &len);
+    CHECK(bytes_eq(bytes, len, "second"));
+
+    /* The value is only used when the child doesn't exist yet. */
+    const RecyclingTreeNode *again = ensure_child(root, "a", "ignored");
+    CHECK(again == a);
+    bytes = recycling_tree_node_value(again, &len);
+    CHECK(bytes_eq(bytes, len, "first"));
+    recycling_tree_node_release(again);
+
+    const RecyclingTreeNode *parent = recycling_tree_node_parent(b);
+    CHECK(parent == a);
+    recycling_tree_node_release(parent);
+
+    recycling_tree_node_addref(b);
+    recycling_tree_node_release(b);
+    recycling_tree_node_release(a);
+
+    /* `a` is kept alive by its child. */
+    report = recycling_tree_gc(tree);
+    CHECK(report.freed == 0);
+    CHECK(drop_count == 0);
+
+    recycling_tree_node_release(b);
+    report = recycling_tree_gc(tree);
+    CHECK(report.processed == 1);
+    CHECK(report.freed == 2);
+    CHECK(drop_count == 2);
+    CHECK(!recycling_tree_maybe_gc(tree, &report));
+
+    /* Nodes outlive the tree as long as they are referenced. */
+    const RecyclingTreeNode *c = ensure_child(root, "c", "third");
+    recycling_tree_free(tree);
+    CHECK(drop_count == 2);
+    bytes = recycling_tree_node_value(c, &len);
+    CHECK(bytes_eq(bytes, len, "third"));
+    recycling_tree_node_release(c);
+    CHECK(drop_count == 3);
+    recycling_tree_node_release(root);
+    CHECK(drop_count == new_count);
+
+    recycling_tree_set_logger(NULL, NULL);
+    return 0;
+}
@@ -0,0 +1,173 @@
+#ifndef RECYCLING_TREE_H
+#define RECYCLING_TREE_H
+
+/* Generated from src/capi.rs by cbindgen, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * A tree of byte strings.
+ */
+typedef struct RecyclingTree RecyclingTree;
+
+/**
+ * A node in a tree of byte strings.
+ */
+typedef struct RecyclingTreeNode RecyclingTreeNode;
+
+/**
+ * A callback called with the address of a node when it is created or freed.
+ */
+typedef void (*RecyclingTreeLogFn)(const struct RecyclingTreeNode *node);
+
+/**
+ * A report of what a run of the garbage collector did, see `GcReport`.
+ */
+typedef struct RecyclingTreeGcReport {
+  /**
+   * The number of free list entries the garbage collector processed.
+   */
+  size_t processed;
+  /**
+   * The number of nodes actually freed.
+   */
+  size_t freed;
+  /**
+   * The number of free list entries that were resurrected in the meantime.
+   */
+  size_t resurrected;
+  /**
+   * The number of free list entries that were too young to be freed.
+   */
+  size_t requeued;
+  /**
+   * How long the garbage collector took, in nanoseconds.
+   */
+  uint64_t elapsed_ns;
+} RecyclingTreeGcReport;
+
+/**
+ * Sets the callbacks called whenever a node is created or freed, in any
+ * tree. Either can be null.
+ */
+void recycling_tree_set_logger(RecyclingTreeLogFn log_new, RecyclingTreeLogFn log_drop);
+
+/**
+ * Creates a new tree whose root has the given value and an empty key.
+ *
+ * # Safety
+ *
+ * `value` must point to `value_len` readable bytes, it can only be null if
+ * `value_len` is 0.
+ */
+struct RecyclingTree *recycling_tree_new(const uint8_t *value, size_t value_len);
+
+/**
+ * Destroys a tree. Nodes still referenced stay valid until released.
+ *
+ * # Safety
+ *
+ * `tree` must come from `recycling_tree_new` and not be used afterwards.
+ */
+void recycling_tree_free(struct RecyclingTree *tree);
+
+/**
+ * Returns a new reference to the root of a tree.
+ *
+ * # Safety
+ *
+ * `tree` must be a live tree.
+ */
+const struct RecyclingTreeNode *recycling_tree_root(const struct RecyclingTree *tree);
+
+/**
+ * Runs the garbage collector of a tree.
+ *
+ * # Safety
+ *
+ * `tree` must be a live tree.
+ */
+struct RecyclingTreeGcReport recycling_tree_gc(const struct RecyclingTree *tree);
+
+/**
+ * Runs the garbage collector of a tree if its GC policy says so, and
+ * returns whether it did. The report is written to `report` if it isn't
+ * null.
+ *
+ * # Safety
+ *
+ * `tree` must be a live tree, and `report` must be null or valid for
+ * writes.
+ */
+bool recycling_tree_maybe_gc(const struct RecyclingTree *tree,
+                             struct RecyclingTreeGcReport *report);
+
+/**
+ * Returns a new reference to the child of a node with the given key,
+ * creating it with the given value if it doesn't exist.
+ *
+ * # Safety
+ *
+ * `node` must be a live node, and `key` and `value` must point to
+ * `key_len` and `value_len` readable bytes respectively.
+ */
+const struct RecyclingTreeNode *recycling_tree_node_ensure_child(const struct RecyclingTreeNode *node,
+                                                                 const uint8_t *key,
+                                                                 size_t key_len,
+                                                                 const uint8_t *value,
+                                                                 size_t value_len);
+
+/**
+ * Returns a new reference to the parent of a node, or null if the node is
+ * the root of its tree.
+ *
+ * # Safety
+ *
+ * `node` must be a live node.
+ */
+const struct RecyclingTreeNode *recycling_tree_node_parent(const struct RecyclingTreeNode *node);
+
+/**
+ * Returns the key of a node and writes its length to `len`.
+ *
+ * # Safety
+ *
+ * `node` must be a live node and `len` must be valid for writes. The
+ * returned bytes live as long as the node.
+ */
+const uint8_t *recycling_tree_node_key(const struct RecyclingTreeNode *node, size_t *len);
+
+/**
+ * Returns the value of a node and writes its length to `len`.
+ *
+ * # Safety
+ *
+ * `node` must be a live node and `len` must be valid for writes. The
+ * returned bytes live as long as the node.
+ */
+const uint8_t *recycling_tree_node_value(const struct RecyclingTreeNode *node, size_t *len);
+
+/**
+ * Adds a reference to a node.
+ *
+ * # Safety
+ *
+ * `node` must be a live node.
+ */
+void recycling_tree_node_addref(const struct RecyclingTreeNode *node);
+
+/**
+ * Releases a reference to a node.
+ *
+ * # Safety
+ *
+ * `node` must be a live node, and the reference released must have been
+ * returned by this API or added with `recycling_tree_node_addref`.
+ */
+void recycling_tree_node_release(const struct RecyclingTreeNode *node);
+
+#endif /* RECYCLING_TREE_H */
@@ -0,0 +1,100 @@
+use crate::logger::{Log, NoopLogger};
+use crate::policy::{FixedCount, GcPolicy};
+use crate::tree::Tree;
+use std::hash::Hash;
+use std::marker::PhantomData as marker;
+use std::sync::Arc;
+
+/// A builder for trees with a custom configuration.
+pub struct TreeBuilder<K, V, Logger = NoopLogger> {
+    pub(crate) gc_policy: Arc<dyn GcPolicy>,
+    pub(crate) gc_generations: usize,
+    pub(crate) free_capacity: Option<usize>,
+    pub(crate) key_index: bool,
+    marker: marker<(K, V, Logger)>,
+}
+
+impl<K, V, Logger> TreeBuilder<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Creates a new builder with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            gc_policy: Arc::new(FixedCount::default()),
+            gc_generations: 0,
+            free_capacity: None,
+            key_index: false,
+            marker,
+        }
+    }
+
+    /// Sets the policy consulted by `Tree::maybe_gc`.
+    pub fn gc_policy(mut self, policy: impl GcPolicy + 'static) -> Self {
+        self.gc_policy = Arc::new(policy);
+        self
+    }
+
+    /// Sets how many garbage collections an unused node must survive while
+    /// waiting on the free list before being freed. Defaults to 0, which
+    /// means unused nodes are freed by the first garbage collection.
+    ///
+    /// Younger nodes are requeued by the garbage collector, so that nodes
+    /// which are frequently reused stay cached. Every collection processing
+    /// a node counts, including each call to `Tree::gc_step`. The age of
+    /// a node waiting on the free list is reset when it is looked up again,
+    /// see `TreeBuilder::free_list_capacity`, and then released. Dropping the
+    /// tree frees all unused nodes regardless of their age.
+    pub fn gc_generations(mut self, generations: usize) -> Self {
+        self.gc_generations = generations;
+        self
+    }
+
+    /// Sets the maximum number of unused nodes kept on the free list, which
+    /// then behaves as an LRU cache. By default, the free list is unbounded.
+    ///
+    /// Whenever a node is released and the free list holds more nodes than
+    /// its capacity, the least recently used ones are evicted and freed until
+    /// it is full again. A node waiting on the free list is used again when
+    /// it is looked up, e.g. with `Node::get_child`, `Node::ensure_child`,
+    /// `Node::find_path` or `WeakNode::upgrade`, and then released. Traversing
+    /// the tree, e.g. with `Node::children` or `Node::descendants`, doesn't
+    /// count as using the nodes traversed.
+    ///
+    /// Evicting a node requires locking the children of its parent, so it is
+    /// postponed to the next release if they are locked already, e.g. when
+    /// nodes are dropped from the closure passed to `Node::ensure_child_with`.
+    pub fn free_list_capacity(mut self, capacity: usize) -> Self {
+        self.free_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets whether the tree maintains an index of its nodes by key, which
+    /// makes `Tree::invalidate` not walk the whole tree. Defaults to false.
+    ///
+    /// The index is updated whenever a node is created or freed.
+    pub fn key_index(mut self, enabled: bool) -> Self {
+        self.key_index = enabled;
+        self
+    }
+
+    /// Creates a new tree from a root value.
+    ///
+    /// The root value can later be accessed through `Tree::root_value`.
+    pub fn build(self, root: V) -> Tree<K, V, Logger> {
+        Tree::from_builder(self, root)
+    }
+}
+
+impl<K, V, Logger> Default for TreeBuilder<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
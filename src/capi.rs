@@ -0,0 +1,300 @@
+//! A C API over trees of byte strings.
+//!
+//! Each node carries a key and a value, both arbitrary byte strings, and the
+//! children of a node are indexed by their key. Nodes are handed out to C as
+//! strong references, see `Node::into_raw`.
+//!
+//! The C header is `include/recycling_tree.h`, and the library C programs
+//! link to is built with e.g.
+//! `cargo rustc --lib --release --features capi --crate-type staticlib`.
+
+use crate::logger::Log;
+use crate::node::Node;
+use crate::report::GcReport;
+use crate::tree::Tree;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A tree of byte strings.
+pub struct RecyclingTree {
+    inner: Tree<ByteKey, ByteValue, CLogger>,
+}
+
+/// A node in a tree of byte strings.
+pub struct RecyclingTreeNode {
+    _private: [u8; 0],
+}
+
+/// A report of what a run of the garbage collector did, see `GcReport`.
+#[repr(C)]
+pub struct RecyclingTreeGcReport {
+    /// The number of free list entries the garbage collector processed.
+    pub processed: usize,
+    /// The number of nodes actually freed.
+    pub freed: usize,
+    /// The number of free list entries that were resurrected in the meantime.
+    pub resurrected: usize,
+    /// The number of free list entries that were too young to be freed.
+    pub requeued: usize,
+    /// How long the garbage collector took, in nanoseconds.
+    pub elapsed_ns: u64,
+}
+
+/// A callback called with the address of a node when it is created or freed.
+pub type RecyclingTreeLogFn = Option<unsafe extern "C" fn(node: *const RecyclingTreeNode)>;
+
+type CNode = Node<ByteKey, ByteValue, CLogger>;
+
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct ByteKey(Arc<[u8]>);
+
+struct ByteValue {
+    key: ByteKey,
+    value: Box<[u8]>,
+}
+
+impl From<&ByteValue> for ByteKey {
+    #[inline]
+    fn from(value: &ByteValue) -> Self {
+        value.key.clone()
+    }
+}
+
+static LOG_NEW: AtomicUsize = AtomicUsize::new(0);
+static LOG_DROP: AtomicUsize = AtomicUsize::new(0);
+
+struct CLogger;
+
+impl CLogger {
+    fn call(callback: &AtomicUsize, ptr: *const c_void) {
+        let callback = callback.load(Ordering::Relaxed);
+        if callback != 0 {
+            let callback = unsafe {
+                mem::transmute::<usize, unsafe extern "C" fn(*const RecyclingTreeNode)>(callback)
+            };
+            unsafe { callback(ptr as *const RecyclingTreeNode) }
+        }
+    }
+}
+
+impl Log for CLogger {
+    fn log_new(ptr: *const c_void) {
+        Self::call(&LOG_NEW, ptr)
+    }
+
+    fn log_drop(ptr: *const c_void) {
+        Self::call(&LOG_DROP, ptr)
+    }
+}
+
+impl From<GcReport> for RecyclingTreeGcReport {
+    fn from(report: GcReport) -> Self {
+        Self {
+            processed: report.processed,
+            freed: report.freed,
+            resurrected: report.resurrected,
+            requeued: report.requeued,
+            elapsed_ns: report.elapsed.as_nanos() as u64,
+        }
+    }
+}
+
+/// Sets the callbacks called whenever a node is created or freed, in any
+/// tree. Either can be null.
+#[no_mangle]
+pub extern "C" fn recycling_tree_set_logger(
+    log_new: RecyclingTreeLogFn,
+    log_drop: RecyclingTreeLogFn,
+) {
+    LOG_NEW.store(log_new.map_or(0, |f| f as usize), Ordering::Relaxed);
+    LOG_DROP.store(log_drop.map_or(0, |f| f as usize), Ordering::Relaxed);
+}
+
+/// Creates a new tree whose root has the given value and an empty key.
+///
+/// # Safety
+///
+/// `value` must point to `value_len` readable bytes, it can only be null if
+/// `value_len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_new(
+    value: *const u8,
+    value_len: usize,
+) -> *mut RecyclingTree {
+    let root = ByteValue {
+        key: ByteKey(Arc::from(&[][..])),
+        value: bytes(value, value_len).into(),
+    };
+    Box::into_raw(Box::new(RecyclingTree { inner: Tree::with_logger(root) }))
+}
+
+/// Destroys a tree. Nodes still referenced stay valid until released.
+///
+/// # Safety
+///
+/// `tree` must come from `recycling_tree_new` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_free(tree: *mut RecyclingTree) {
+    drop(Box::from_raw(tree));
+}
+
+/// Returns a new reference to the root of a tree.
+///
+/// # Safety
+///
+/// `tree` must be a live tree.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_root(
+    tree: *const RecyclingTree,
+) -> *const RecyclingTreeNode {
+    (*tree).inner.root().clone().into_raw() as *const RecyclingTreeNode
+}
+
+/// Runs the garbage collector of a tree.
+///
+/// # Safety
+///
+/// `tree` must be a live tree.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_gc(tree: *const RecyclingTree) -> RecyclingTreeGcReport {
+    (*tree).inner.gc().into()
+}
+
+/// Runs the garbage collector of a tree if its GC policy says so, and
+/// returns whether it did. The report is written to `report` if it isn't
+/// null.
+///
+/// # Safety
+///
+/// `tree` must be a live tree, and `report` must be null or valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_maybe_gc(
+    tree: *const RecyclingTree,
+    report: *mut RecyclingTreeGcReport,
+) -> bool {
+    match (*tree).inner.maybe_gc() {
+        Some(gc_report) => {
+            if !report.is_null() {
+                report.write(gc_report.into());
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns a new reference to the child of a node with the given key,
+/// creating it with the given value if it doesn't exist.
+///
+/// # Safety
+///
+/// `node` must be a live node, and `key` and `value` must point to
+/// `key_len` and `value_len` readable bytes respectively.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_node_ensure_child(
+    node: *const RecyclingTreeNode,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> *const RecyclingTreeNode {
+    let key = ByteKey(Arc::from(bytes(key, key_len)));
+    let child = with_node(node, |node| {
+        node.ensure_child_with(key.clone(), || ByteValue {
+            key,
+            value: bytes(value, value_len).into(),
+        })
+    });
+    child.into_raw() as *const RecyclingTreeNode
+}
+
+/// Returns a new reference to the parent of a node, or null if the node is
+/// the root of its tree.
+///
+/// # Safety
+///
+/// `node` must be a live node.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_node_parent(
+    node: *const RecyclingTreeNode,
+) -> *const RecyclingTreeNode {
+    match with_node(node, |node| node.parent()) {
+        Some(parent) => parent.into_raw() as *const RecyclingTreeNode,
+        None => ptr::null(),
+    }
+}
+
+/// Returns the key of a node and writes its length to `len`.
+///
+/// # Safety
+///
+/// `node` must be a live node and `len` must be valid for writes. The
+/// returned bytes live as long as the node.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_node_key(
+    node: *const RecyclingTreeNode,
+    len: *mut usize,
+) -> *const u8 {
+    let key = &node_value(node).key.0;
+    len.write(key.len());
+    key.as_ptr()
+}
+
+/// Returns the value of a node and writes its length to `len`.
+///
+/// # Safety
+///
+/// `node` must be a live node and `len` must be valid for writes. The
+/// returned bytes live as long as the node.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_node_value(
+    node: *const RecyclingTreeNode,
+    len: *mut usize,
+) -> *const u8 {
+    let value = &node_value(node).value;
+    len.write(value.len());
+    value.as_ptr()
+}
+
+/// Adds a reference to a node.
+///
+/// # Safety
+///
+/// `node` must be a live node.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_node_addref(node: *const RecyclingTreeNode) {
+    mem::forget(with_node(node, CNode::clone));
+}
+
+/// Releases a reference to a node.
+///
+/// # Safety
+///
+/// `node` must be a live node, and the reference released must have been
+/// returned by this API or added with `recycling_tree_node_addref`.
+#[no_mangle]
+pub unsafe extern "C" fn recycling_tree_node_release(node: *const RecyclingTreeNode) {
+    drop(CNode::from_raw(node as *const c_void));
+}
+
+unsafe fn with_node<R>(node: *const RecyclingTreeNode, f: impl FnOnce(&CNode) -> R) -> R {
+    CNode::with_raw(node as *const c_void, f)
+}
+
+unsafe fn node_value<'a>(node: *const RecyclingTreeNode) -> &'a ByteValue {
+    // The value lives as long as the node itself.
+    with_node(node, |node| &*(node.value() as *const ByteValue))
+}
+
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
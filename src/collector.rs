@@ -0,0 +1,145 @@
+use crate::logger::Log;
+use crate::policy::GcPolicy;
+use crate::report::GcReport;
+use crate::tree::Tree;
+use crate::weak::WeakNode;
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use std::hash::Hash;
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A handle to a background thread running the garbage collector of a tree.
+///
+/// The thread wakes up at a regular interval and runs the garbage collector
+/// whenever the GC policy of the tree says so, unless another collection is
+/// already ongoing. It doesn't keep the tree alive, and stops as soon as the
+/// tree is dropped. Dropping the handle stops the thread and waits for it to
+/// finish.
+pub struct Collector {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The state shared by a collector, its thread and its tree.
+pub(crate) struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// Whether the thread should stop, because either the handle or the
+    /// tree was dropped.
+    stopping: bool,
+    /// Whether the thread stopped.
+    finished: bool,
+    last_report: Option<GcReport>,
+}
+
+impl Collector {
+    /// Spawns a new thread collecting the given tree, checking its GC policy
+    /// every `interval`.
+    pub fn spawn<K, V, Logger>(tree: &Tree<K, V, Logger>, interval: Duration) -> io::Result<Self>
+    where
+        K: Eq + Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        for<'a> &'a V: Into<K>,
+        Logger: Log + Send + Sync + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            condvar: Condvar::new(),
+        });
+        let root = tree.root().downgrade();
+        let policy = tree.gc_policy().clone();
+        let min_age = tree.gc_generations();
+        let thread = thread::Builder::new()
+            .name("recycling-tree-gc".into())
+            .spawn({
+                let shared = shared.clone();
+                move || shared.run(root, &*policy, min_age, interval)
+            })?;
+        tree.register_collector(&shared);
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the report of the last garbage collection run by this
+    /// collector, if any.
+    pub fn last_report(&self) -> Option<GcReport> {
+        self.shared.state.lock().last_report
+    }
+
+    /// Returns whether the thread stopped because the tree was dropped.
+    pub fn is_finished(&self) -> bool {
+        self.shared.state.lock().finished
+    }
+
+    /// Stops the thread and waits for it to finish.
+    ///
+    /// This is equivalent to dropping the handle.
+    pub fn stop(self) {}
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        self.shared.stop();
+        if let Some(thread) = self.thread.take() {
+            // The thread only panics if the GC policy does, in which case
+            // there is nothing left to stop anyway.
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    /// Tells the thread to stop, waking it up if it is waiting.
+    pub(crate) fn stop(&self) {
+        self.state.lock().stopping = true;
+        self.condvar.notify_one();
+    }
+
+    fn run<K, V, Logger>(
+        &self,
+        root: WeakNode<K, V, Logger>,
+        policy: &dyn GcPolicy,
+        min_age: usize,
+        interval: Duration,
+    ) where
+        K: Eq + Hash,
+        for<'a> &'a V: Into<K>,
+        Logger: Log,
+    {
+        let mut state = self.state.lock();
+        loop {
+            if !state.stopping {
+                self.condvar.wait_for(&mut state, interval);
+            }
+            if state.stopping {
+                break;
+            }
+            // The lock isn't held while collecting, so that stopping the
+            // collector doesn't need to wait for the collection to finish.
+            let report = MutexGuard::unlocked(&mut state, || {
+                let root = root.upgrade()?;
+                let stats = root.tree_gc_stats()?;
+                if policy.should_collect(&stats) {
+                    Some(root.tree_try_gc(min_age))
+                } else {
+                    Some(None)
+                }
+            });
+            match report {
+                Some(Some(report)) => state.last_report = Some(report),
+                Some(None) => {}
+                // The tree was dropped.
+                None => break,
+            }
+        }
+        state.finished = true;
+    }
+}
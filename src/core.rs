@@ -1,14 +1,31 @@
 use crate::ancestors::Ancestors;
+use crate::builder::TreeBuilder;
+use crate::error::TryEnsureChildError;
+use crate::iter::{AncestorNodes, AncestorValues, Descendants, Traversal};
 use crate::logger::Log;
 use crate::map::Map;
 use crate::node::{Node, UnsafeNode};
+use crate::node_ref::NodeRef;
+use crate::policy::GcStats;
+use crate::report::GcReport;
 use crate::tree::Tree;
-use parking_lot::{RwLock, RwLockUpgradableReadGuard};
+use crate::visitor::Visitor;
+use crate::weak::{WeakCell, WeakNode};
+use fxhash::FxHashMap;
+use parking_lot::{
+    Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard,
+};
+use std::borrow::Borrow;
+use std::collections::hash_map::Entry;
+use std::collections::TryReserveError;
 use std::ffi::c_void;
 use std::hash::Hash;
 use std::marker::PhantomData as marker;
+use std::mem::{self, ManuallyDrop};
 use std::ptr::{self, NonNull};
-use std::sync::atomic::{self, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// The inner contents of a node.
 pub(crate) struct NodeInner<K, V, Logger> {
@@ -18,8 +35,11 @@ pub(crate) struct NodeInner<K, V, Logger> {
     /// The children of this node. Children remove themselves from this map
     /// on drop either after the free list is gone or when the tree is GC'd.
     children: RwLock<Map<K, UnsafeNode<K, V, Logger>>>,
-    /// The reference counter of this node. Starts at 1, is reincremented back
-    /// to 1 after reaching 0 if it is put on the free list.
+    /// The reference counter of this node. Starts at 1. When the last
+    /// reference to a node is dropped, it is transferred to the free list
+    /// instead of being released, so this only reaches 0 when the node is
+    /// removed from the free list by the GC or if the free list is gone,
+    /// right before the node is freed.
     refcount: AtomicUsize,
     /// This field has two different meanings depending on whether this node
     /// is the root of the tree or not.
@@ -38,119 +58,1465 @@ pub(crate) struct NodeInner<K, V, Logger> {
     /// Starts as `NodeInner::DANGLING_PTR` for root nodes and the null pointer
     /// for non-root nodes.
     next_free: AtomicPtr<NodeInner<K, V, Logger>>,
-    /// The length of the free list. Only used on root nodes.
+    /// The previous item of the free list if this node is waiting on
+    /// a bounded free list, `NodeInner::DANGLING_PTR` if it is its first
+    /// item, and null otherwise. Only accessed with the free list locked.
+    prev_free: AtomicPtr<NodeInner<K, V, Logger>>,
+    /// The number of garbage collections this node survived while waiting
+    /// on the free list. Only used on non-root nodes.
+    gc_age: AtomicUsize,
+    /// The state of the tree, only set on root nodes.
+    state: Option<Box<TreeState<K, V, Logger>>>,
+    /// The cell shared with the weak references to this node, created when
+    /// the first one is. This holds a strong reference to the cell, which is
+    /// emptied when the node is freed.
+    weak_cell: AtomicPtr<WeakCell<K, V, Logger>>,
+    /// Whether this node is pinned, in which case its ancestors are too. A
+    /// pinned node holds a reference to itself that is never released, and
+    /// references to it are created and dropped without touching its
+    /// refcount.
+    pinned: AtomicBool,
+    /// Whether this node was looked up while waiting on the free list since
+    /// a reference to it was last released, see `UnsafeNode::on_lookup`.
+    looked_up: AtomicBool,
+}
+
+/// The state of a tree which is only needed on its root node, allocated
+/// separately so that it doesn't take room in every node.
+struct TreeState<K, V, Logger> {
+    /// The length of the free list.
     free_count: AtomicUsize,
+    /// The maximum length of the free list, if any.
+    free_capacity: Option<usize>,
+    /// The last item of the free list if it is bounded and not empty, and
+    /// null otherwise. Only accessed with the free list locked.
+    free_tail: AtomicPtr<NodeInner<K, V, Logger>>,
+    /// The number of allocated nodes in the tree, the root excluded.
+    node_count: AtomicUsize,
+    /// The nodes of the tree by key, if enabled.
+    key_index: Option<KeyIndex<K, V, Logger>>,
+    /// The number of ongoing collections of the free list.
+    collecting: AtomicUsize,
+}
+
+impl<K, V, Logger> TreeState<K, V, Logger>
+where
+    K: Eq + Hash,
+{
+    /// Marks a collection of the free list of this tree as ongoing.
+    fn start_collecting(&self) -> CollectingGuard<'_> {
+        self.collecting.fetch_add(1, Ordering::Acquire);
+        CollectingGuard(&self.collecting)
+    }
+
+    /// Marks a collection of the free list of this tree as ongoing, unless
+    /// another one is already.
+    fn try_start_collecting(&self) -> Option<CollectingGuard<'_>> {
+        self.collecting
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        Some(CollectingGuard(&self.collecting))
+    }
+
+    /// Locks the key index of this tree, if it has one.
+    fn lock_key_index(&self) -> Option<KeyIndexGuard<'_, K, V, Logger>> {
+        self.key_index.as_ref().map(Mutex::lock)
+    }
+
+    /// Locks the key index of this tree, if it has one, and reserves room in
+    /// it for a node with the given key without aborting if memory
+    /// allocation fails.
+    fn try_lock_key_index(
+        &self,
+        key: K,
+    ) -> Result<Option<KeyIndexGuard<'_, K, V, Logger>>, TryReserveError> {
+        let mut key_index = match self.lock_key_index() {
+            Some(key_index) => key_index,
+            None => return Ok(None),
+        };
+        // `HashMap::entry` reserves room for vacant entries itself, which
+        // must not abort.
+        key_index.try_reserve(1)?;
+        match key_index.entry(key) {
+            Entry::Occupied(mut entry) => entry.get_mut().try_reserve(1)?,
+            Entry::Vacant(entry) => {
+                let mut nodes = Vec::new();
+                nodes.try_reserve(1)?;
+                entry.insert(nodes);
+            }
+        }
+        Ok(Some(key_index))
+    }
+}
+
+/// An index of the non-root nodes of a tree by key.
+type KeyIndex<K, V, Logger> = Mutex<FxHashMap<K, Vec<UnsafeNode<K, V, Logger>>>>;
+
+/// A locked key index, see `KeyIndex`.
+type KeyIndexGuard<'a, K, V, Logger> = MutexGuard<'a, FxHashMap<K, Vec<UnsafeNode<K, V, Logger>>>>;
+
+/// Marks a collection of the free list of a tree as ongoing for as long as
+/// it is alive.
+struct CollectingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for CollectingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// A chain of nodes detached from the free list of a tree, which will be
+/// requeued on it.
+struct FreeChain<K, V, Logger> {
+    head: *mut NodeInner<K, V, Logger>,
+    tail: *mut NodeInner<K, V, Logger>,
+}
+
+/// The rest of a chain of nodes detached from the free list of a tree by
+/// `Tree::retain`, and the nodes retained so far, which are all requeued when
+/// this is dropped, e.g. if the predicate panics.
+struct RetainGuard<'a, K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'b> &'b V: Into<K>,
+    Logger: Log,
+{
+    root: &'a UnsafeNode<K, V, Logger>,
+    rest: *mut NodeInner<K, V, Logger>,
+    retained: FreeChain<K, V, Logger>,
+}
+
+impl<K, V> Tree<K, V>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+{
+    /// Creates a new tree from a root value.
+    ///
+    /// The root value can later be accessed through `Tree::root_value`.
+    pub fn new(root: V) -> Self {
+        Self::with_logger(root)
+    }
+}
+
+impl<K, V, Logger> Tree<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Creates a new tree from a root value, with a custom logger.
+    ///
+    /// The root value can later be accessed through `Tree::root_value`.
+    pub fn with_logger(root: V) -> Self {
+        TreeBuilder::new().build(root)
+    }
+
+    /// Returns a new builder to create a tree with a custom configuration.
+    pub fn builder() -> TreeBuilder<K, V, Logger> {
+        TreeBuilder::new()
+    }
+
+    pub(crate) fn from_builder(builder: TreeBuilder<K, V, Logger>, root: V) -> Self {
+        let tree = unsafe {
+            Tree::from_root_node(
+                Node::from_unsafe_node(UnsafeNode::new(NodeInner {
+                    value: root,
+                    marker,
+                    ancestors: None,
+                    children: Default::default(),
+                    refcount: AtomicUsize::new(1),
+                    next_free: AtomicPtr::new(NodeInner::DANGLING_PTR),
+                    prev_free: AtomicPtr::new(ptr::null_mut()),
+                    gc_age: Default::default(),
+                    state: Some(Box::new(TreeState {
+                        free_count: Default::default(),
+                        free_capacity: builder.free_capacity,
+                        free_tail: AtomicPtr::new(ptr::null_mut()),
+                        node_count: Default::default(),
+                        key_index: if builder.key_index { Some(Default::default()) } else { None },
+                        collecting: Default::default(),
+                    })),
+                    weak_cell: AtomicPtr::new(ptr::null_mut()),
+                    pinned: AtomicBool::new(false),
+                    looked_up: AtomicBool::new(false),
+                })),
+                builder.gc_policy,
+                builder.gc_generations,
+            )
+        };
+        Logger::log_new(tree.root().as_ptr());
+        tree
+    }
 }
 
-/// The threshold over which `Tree::maybe_gc` will trigger a GC. Nobody knows
-/// why it is this value, not even Gecko people.
-const GC_COUNT_THRESHOLD: usize = 300;
+impl<K, V, Logger> Tree<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Runs the garbage collector of the tree's free list if needed according
+    /// to the tree's GC policy.
+    ///
+    /// The garbage collector doesn't run either if another collection is
+    /// already ongoing, as with `Tree::try_gc`.
+    ///
+    /// Returns a report of what the garbage collector did, or `None` if it
+    /// didn't run.
+    pub fn maybe_gc(&self) -> Option<GcReport> {
+        if self.gc_policy().should_collect(&self.gc_stats()) {
+            self.try_gc()
+        } else {
+            None
+        }
+    }
+
+    /// Runs the garbage collector of the tree's free list.
+    ///
+    /// Multiple threads can collect the free list concurrently: each
+    /// collection atomically detaches the whole free list, so the first one
+    /// collects all the nodes that were waiting on it, and the other ones
+    /// only collect the nodes pushed on it in the meantime, if any. A node is
+    /// never collected twice and nodes can be released and resurrected by
+    /// other threads during a collection.
+    ///
+    /// Returns a report of what the garbage collector did.
+    pub fn gc(&self) -> GcReport {
+        let root = self.as_unsafe_node();
+        let _collecting = root.state().start_collecting();
+        unsafe {
+            UnsafeNode::swap_free_list_and_gc(root, NodeInner::DANGLING_PTR, self.gc_generations())
+        }
+    }
+
+    /// Runs the garbage collector of the tree's free list, unless another
+    /// collection is already ongoing.
+    ///
+    /// This counts collections by `Tree::gc`, `Tree::gc_step`, `Tree::gc_for`,
+    /// `Tree::maybe_gc` and by collector threads, but not the eviction of
+    /// nodes from a bounded free list. A collection starting concurrently
+    /// with this one is not prevented from running, see `Tree::gc`.
+    ///
+    /// Returns a report of what the garbage collector did, or `None` if it
+    /// didn't run.
+    pub fn try_gc(&self) -> Option<GcReport> {
+        let root = self.as_unsafe_node();
+        let _collecting = root.state().try_start_collecting()?;
+        Some(unsafe {
+            UnsafeNode::swap_free_list_and_gc(root, NodeInner::DANGLING_PTR, self.gc_generations())
+        })
+    }
+
+    /// Detaches all the nodes with the given key from the children of their
+    /// parents, wherever they are in the tree.
+    ///
+    /// Later calls to `Node::ensure_child` and friends then create new nodes
+    /// with that key, while existing references to the detached nodes stay
+    /// valid until they are dropped. Detached nodes which are unused are
+    /// freed by the next garbage collection.
+    ///
+    /// This uses the key index of the tree if it was enabled with
+    /// `TreeBuilder::key_index`, and walks the whole tree otherwise.
+    ///
+    /// Returns the number of nodes that were detached.
+    pub fn invalidate(&self, key: &K) -> usize {
+        let root = self.as_unsafe_node();
+        let nodes = match &root.state().key_index {
+            Some(key_index) => {
+                // The nodes are kept in the key index until they are freed,
+                // so the index must stay locked while creating references to
+                // them. It is only locked for that, given it is locked while
+                // the children of their parents are, whenever nodes are
+                // created or freed.
+                let mut key_index = key_index.lock();
+                let nodes = key_index.remove(key).unwrap_or_default();
+                nodes
+                    .iter()
+                    .filter_map(|node| unsafe { UnsafeNode::upgrade(node) })
+                    .collect::<Vec<_>>()
+            }
+            None => UnsafeNode::descendants_with_key(root, key),
+        };
+        nodes.iter().filter(|node| node.detach()).count()
+    }
+
+    /// Frees the unused nodes of the tree's free list for which `f` returns
+    /// false, leaving the other ones queued.
+    ///
+    /// Nodes which are waiting on the free list but were resurrected are
+    /// never freed by this, and their parents are freed in cascade if they
+    /// aren't used anymore, as with `Tree::gc`.
+    ///
+    /// Returns a report of what the garbage collector did, where the nodes
+    /// for which `f` returned true are counted as requeued.
+    ///
+    /// If `f` panics, the nodes it wasn't called on yet are requeued along
+    /// with the ones it returned true for.
+    pub fn retain(&self, mut f: impl FnMut(&V) -> bool) -> GcReport {
+        let start = Instant::now();
+        let root = self.as_unsafe_node();
+        let _collecting = root.state().start_collecting();
+        let mut report = GcReport::default();
+        let head = match unsafe { UnsafeNode::swap_free_list(root, NodeInner::DANGLING_PTR) } {
+            Some(head) => head,
+            None => {
+                report.elapsed = start.elapsed();
+                return report;
+            }
+        };
+        let mut chain = RetainGuard {
+            root,
+            rest: head,
+            retained: FreeChain::new(),
+        };
+        while chain.rest != NodeInner::DANGLING_PTR {
+            let node = unsafe { UnsafeNode::from_raw(chain.rest) };
+            report.processed += 1;
+            // The node is only detached from the rest of the chain once `f`
+            // returned, so that it is requeued with it if `f` panics.
+            let retain = f(&node.value);
+            let next = node.next_free.swap(ptr::null_mut(), Ordering::Relaxed);
+            if retain {
+                unsafe { chain.retained.push_back(chain.rest) };
+                report.requeued += 1;
+            } else {
+                root.state().free_count.fetch_sub(1, Ordering::Relaxed);
+                let freed = unsafe { UnsafeNode::release(&node) };
+                report.freed += freed;
+                if freed == 0 {
+                    report.resurrected += 1;
+                }
+            }
+            chain.rest = next;
+        }
+        let retained = mem::replace(&mut chain.retained, FreeChain::new());
+        unsafe { retained.requeue(root, &mut report) };
+        report.elapsed = start.elapsed();
+        report
+    }
+
+    /// Runs the garbage collector on at most `max_nodes` entries of the
+    /// tree's free list, leaving the other ones queued.
+    ///
+    /// The most recently freed nodes are processed first. Returns a report of
+    /// what the garbage collector did.
+    pub fn gc_step(&self, max_nodes: usize) -> GcReport {
+        let start = Instant::now();
+        if max_nodes == 0 {
+            return GcReport::default();
+        }
+        let root = self.as_unsafe_node();
+        let _collecting = root.state().start_collecting();
+        let bounded = root.state().free_capacity.is_some();
+        let head = match UnsafeNode::lock_free_list(root) {
+            Some(head) => head,
+            None => return GcReport::default(),
+        };
+        // Find the last entry we will collect, the lock prevents anyone from
+        // detaching entries from the free list in the meantime.
+        let mut last = head;
+        let mut rest = head;
+        for _ in 0..max_nodes {
+            if rest == NodeInner::DANGLING_PTR {
+                break;
+            }
+            last = rest;
+            unsafe {
+                rest = (*last).next_free.load(Ordering::Relaxed);
+                if bounded {
+                    (*last).prev_free.store(ptr::null_mut(), Ordering::Relaxed);
+                }
+            }
+        }
+        if last == rest {
+            // The free list is empty, just unlock it.
+            root.next_free.store(head, Ordering::Release);
+            return GcReport {
+                elapsed: start.elapsed(),
+                ..GcReport::default()
+            };
+        }
+        // Cut the chain we will collect from the rest of the free list and
+        // unlock the free list with the remaining entries.
+        unsafe {
+            (*last).next_free.store(NodeInner::DANGLING_PTR, Ordering::Relaxed);
+            if bounded {
+                UnsafeNode::set_prev_free(root, rest, NodeInner::DANGLING_PTR);
+            }
+        }
+        root.next_free.store(rest, Ordering::Release);
+        let mut report = unsafe {
+            UnsafeNode::collect_free_chain(root, head, self.gc_generations())
+        };
+        report.elapsed = start.elapsed();
+        report
+    }
+
+    /// Runs the garbage collector on the tree's free list in small steps
+    /// until it is empty or the given time budget is exceeded.
+    ///
+    /// The budget is only checked between steps, so this may run for a bit
+    /// longer than it. Returns a report of what the garbage collector did.
+    pub fn gc_for(&self, budget: Duration) -> GcReport {
+        /// The number of free list entries collected in each step.
+        const STEP: usize = 32;
+
+        let start = Instant::now();
+        let mut report = GcReport::default();
+        loop {
+            let step = self.gc_step(STEP);
+            report.processed += step.processed;
+            report.freed += step.freed;
+            report.resurrected += step.resurrected;
+            report.requeued += step.requeued;
+            report.elapsed = start.elapsed();
+            // Entries are requeued at the head of the free list, so we stop
+            // there instead of aging them again right away.
+            if step.processed < STEP || step.requeued != 0 || report.elapsed >= budget {
+                return report;
+            }
+        }
+    }
+
+    /// Returns the number of nodes currently waiting on the tree's free list.
+    ///
+    /// This is only an approximation if other threads are concurrently
+    /// dropping nodes or running the garbage collector.
+    pub fn free_list_len(&self) -> usize {
+        self.as_unsafe_node().state().free_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the current statistics of the tree, as given to its GC policy.
+    pub fn gc_stats(&self) -> GcStats {
+        self.as_unsafe_node().gc_stats()
+    }
+
+    /// Returns whether any node of the tree is pinned, in which case dropping
+    /// the tree leaks the pinned nodes, see `Node::pin`.
+    pub fn has_pinned(&self) -> bool {
+        // The root is pinned whenever any of its descendants is.
+        self.root().is_pinned()
+    }
+}
+
+impl<K, V, Logger> Drop for Tree<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    fn drop(&mut self) {
+        unsafe {
+            UnsafeNode::swap_free_list_and_gc(self.as_unsafe_node(), ptr::null_mut(), 0);
+        }
+        #[cfg(feature = "collector")]
+        self.stop_collectors();
+    }
+}
+
+impl<K, V, Logger> Node<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Removes this node from the children of its parent, if it is still
+    /// there. Returns whether it was.
+    fn detach(&self) -> bool {
+        let this = self.as_unsafe_node();
+        let parent = match this.parent() {
+            Some(parent) => parent,
+            None => return false,
+        };
+        UnsafeNode::remove_from_children(this, &mut parent.children.write())
+    }
+
+    /// Returns the child of this node with the given key, if any.
+    ///
+    /// Unlike `Node::ensure_child`, this never creates a new child. Children
+    /// waiting on the free list of the tree are found too.
+    pub fn get_child(&self, key: &K) -> Option<Node<K, V, Logger>> {
+        let children = self.as_unsafe_node().children.read();
+        let child = children.get(key, |node| node.key())?;
+        Some(unsafe { UnsafeNode::look_up_from_children(child) })
+    }
+
+    /// Ensures that a child exists in this node with the given value.
+    ///
+    /// If a child with this value was already created since last GC happened,
+    /// that child is returned instead.
+    pub fn ensure_child(&self, value: V) -> Node<K, V, Logger> {
+        let key = (&value).into();
+        self.ensure_child_with(key, || value)
+    }
+
+    /// Ensures that a child exists in this node with the given key, calling
+    /// `make` to create its value if it doesn't.
+    ///
+    /// This is like `Node::ensure_child` except that the value is only ever
+    /// created if there is no child with that key yet. The key of the created
+    /// value should be equal to `key`, which is only checked in debug builds.
+    pub fn ensure_child_with(&self, key: K, make: impl FnOnce() -> V) -> Node<K, V, Logger> {
+        let this = self.as_unsafe_node();
+        let children = this.children.upgradable_read();
+        if let Some(child) = children.get(&key, |node| node.key()) {
+            return unsafe { UnsafeNode::look_up_from_children(child) };
+        }
+        let mut children = RwLockUpgradableReadGuard::upgrade(children);
+        let unsafe_node = children.get_or_insert_with(
+            key,
+            |node| node.key(),
+            |key| {
+                let value = make();
+                debug_assert!((&value).into() == *key, "value doesn't match the child key");
+                let root = unsafe { UnsafeNode::clone(this.root().unwrap_or(this)) };
+                UnsafeNode::new(NodeInner {
+                    ancestors: Some(unsafe { Ancestors::new(root, self.clone()) }),
+                    ..NodeInner::new_child(value)
+                })
+            },
+        );
+        let node = unsafe { Node::from_unsafe_node(UnsafeNode::clone(unsafe_node)) };
+        UnsafeNode::on_new_child(node.as_unsafe_node(), None);
+        node
+    }
+
+    /// Ensures that a child exists in this node with the given key, calling
+    /// `make` to create its value if it doesn't, without aborting if memory
+    /// allocation fails.
+    ///
+    /// If `make` returns an error, or if memory for the new child cannot be
+    /// allocated, the children of this node are left untouched and the error
+    /// is returned.
+    pub fn try_ensure_child_with<E>(
+        &self,
+        key: K,
+        make: impl FnOnce() -> Result<V, E>,
+    ) -> Result<Node<K, V, Logger>, TryEnsureChildError<E>> {
+        let this = self.as_unsafe_node();
+        let children = this.children.upgradable_read();
+        if let Some(child) = children.get(&key, |node| node.key()) {
+            return Ok(unsafe { UnsafeNode::look_up_from_children(child) });
+        }
+        let mut children = RwLockUpgradableReadGuard::upgrade(children);
+        let mut key_index = None;
+        let unsafe_node = children.try_get_or_insert_with(
+            key,
+            |node| node.key(),
+            |key| {
+                let value = make().map_err(TryEnsureChildError::Value)?;
+                debug_assert!((&value).into() == *key, "value doesn't match the child key");
+                // The ancestors are only set once everything that can fail is
+                // done, given they hold a reference to this node that would
+                // need to be released otherwise.
+                let mut unsafe_node = UnsafeNode::try_new(NodeInner::new_child(value))
+                    .map_err(|_| TryEnsureChildError::Alloc)?;
+                // The key index stays locked until the node is registered in
+                // it, so that nobody else uses the room we reserved there.
+                match this.root().unwrap_or(this).state().try_lock_key_index(unsafe_node.key()) {
+                    Ok(guard) => key_index = guard,
+                    Err(_) => {
+                        unsafe { UnsafeNode::drop(&mut unsafe_node) };
+                        return Err(TryEnsureChildError::Alloc);
+                    }
+                }
+                let root = unsafe { UnsafeNode::clone(this.root().unwrap_or(this)) };
+                unsafe {
+                    UnsafeNode::deref_mut(&mut unsafe_node).ancestors =
+                        Some(Ancestors::new(root, self.clone()));
+                }
+                Ok(unsafe_node)
+            },
+        )?;
+        let node = unsafe { Node::from_unsafe_node(UnsafeNode::clone(unsafe_node)) };
+        UnsafeNode::on_new_child(node.as_unsafe_node(), key_index);
+        Ok(node)
+    }
+
+    /// Ensures that a path of children exists from this node with the given
+    /// values, and returns the last node of that path.
+    ///
+    /// This is equivalent to calling `Node::ensure_child` for each value in
+    /// turn, except that the intermediate nodes are kept alive by locking
+    /// the children of their parent instead of by creating new references
+    /// to them, so their refcount is only touched when a child is created.
+    ///
+    /// If `values` is empty, this returns a new reference to this node.
+    pub fn ensure_path<I>(&self, values: I) -> Node<K, V, Logger>
+    where
+        I: IntoIterator<Item = V>,
+    {
+        // The locked children of the parent of `node`, which guarantees that
+        // `node` cannot be freed while we walk through it. This is `None` as
+        // long as `node` is `self`.
+        let mut parent_children = None::<RwLockReadGuard<Map<K, UnsafeNode<K, V, Logger>>>>;
+        let mut node = unsafe { UnsafeNode::clone(self.as_unsafe_node()) };
+        // Whether we own the initial reference of `node`, which is the case
+        // when we just created it.
+        let mut owned = false;
+        for value in values {
+            // The inner node outlives the `node` variable for as long as
+            // `parent_children` is held, and its children lock is only
+            // released after `parent_children` is replaced by it.
+            let this = unsafe { &*(&*node as *const NodeInner<K, V, Logger>) };
+            let children = this.children.upgradable_read();
+            let key = (&value).into();
+            let (children, child) = if let Some(child) = children.get(&key, |node| node.key()) {
+                if owned {
+                    // Another thread managed to create a child in the node
+                    // we just created. That child holds a reference to `node`
+                    // so releasing ours cannot make the refcount reach 0.
+                    let old_refcount = node.refcount.fetch_sub(1, Ordering::Release);
+                    debug_assert!(old_refcount > 1);
+                }
+                let child = unsafe { UnsafeNode::clone(child) };
+                owned = false;
+                (RwLockUpgradableReadGuard::downgrade(children), child)
+            } else {
+                let mut children = RwLockUpgradableReadGuard::upgrade(children);
+                let child = children.get_or_insert_with(
+                    key,
+                    |node| node.key(),
+                    |_| {
+                        let parent = unsafe {
+                            if owned {
+                                Node::from_unsafe_node(UnsafeNode::clone(&node))
+                            } else if parent_children.is_some() {
+                                UnsafeNode::to_node_from_children(&node)
+                            } else {
+                                self.clone()
+                            }
+                        };
+                        let root = unsafe { UnsafeNode::clone(node.root().unwrap_or(&node)) };
+                        UnsafeNode::new(NodeInner {
+                            ancestors: Some(unsafe { Ancestors::new(root, parent) }),
+                            ..NodeInner::new_child(value)
+                        })
+                    },
+                );
+                let child = unsafe { UnsafeNode::clone(child) };
+                UnsafeNode::on_new_child(&child, None);
+                owned = true;
+                (RwLockWriteGuard::downgrade(children), child)
+            };
+            // From now on, `node` is kept alive by `child`, which holds
+            // a reference to it and cannot be removed from `children` while
+            // we hold them.
+            parent_children = Some(children);
+            node = child;
+        }
+        unsafe {
+            if owned {
+                Node::from_unsafe_node(node)
+            } else if parent_children.is_some() {
+                UnsafeNode::look_up_from_children(&node)
+            } else {
+                self.clone()
+            }
+        }
+    }
+}
+
+impl<K, V, Logger> Node<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Returns the node at the end of the given path of keys from this node,
+    /// if all of them exist.
+    ///
+    /// This never creates any node. If `keys` is empty, this returns a new
+    /// reference to this node.
+    pub fn find_path<I>(&self, keys: I) -> Option<Node<K, V, Logger>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let (node, _, complete) = self.walk_path(keys);
+        if complete {
+            Some(node)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the deepest node that exists along the given path of keys from
+    /// this node, with the number of keys that were matched to reach it.
+    ///
+    /// This never creates any node. If the first key doesn't match any child,
+    /// this returns a new reference to this node and 0.
+    pub fn longest_prefix<I>(&self, keys: I) -> (Node<K, V, Logger>, usize)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let (node, matched, _) = self.walk_path(keys);
+        (node, matched)
+    }
+
+    /// Returns a snapshot of the children of this node.
+    ///
+    /// Children waiting on the free list of the tree are included.
+    pub fn children(&self) -> Vec<Node<K, V, Logger>> {
+        let children = self.as_unsafe_node().children.read();
+        children
+            .values()
+            .map(|child| unsafe { UnsafeNode::to_node_from_children(child) })
+            .collect()
+    }
+
+    /// Returns the number of children of this node, including the ones
+    /// waiting on the free list of the tree.
+    pub fn child_count(&self) -> usize {
+        self.as_unsafe_node().children.read().len()
+    }
+
+    /// Returns whether this node has any children, including the ones
+    /// waiting on the free list of the tree.
+    pub fn has_children(&self) -> bool {
+        !self.as_unsafe_node().children.read().is_empty()
+    }
+
+    /// Returns an iterator over the descendants of this node, in the given
+    /// order. This node itself is not included.
+    pub fn descendants(&self, traversal: Traversal) -> Descendants<K, V, Logger> {
+        Descendants::new(self, traversal)
+    }
+
+    /// Walks the subtree rooted at this node depth-first, this node included,
+    /// calling the visitor when entering and leaving each node.
+    ///
+    /// Like with `Node::descendants`, the children of each node are
+    /// snapshotted when the node is entered.
+    pub fn walk(&self, visitor: &mut impl Visitor<K, V, Logger>) {
+        visitor.enter(self);
+        let mut stack = vec![(self.clone(), self.children().into_iter())];
+        while let Some((_, children)) = stack.last_mut() {
+            match children.next() {
+                Some(child) => {
+                    visitor.enter(&child);
+                    let grandchildren = child.children().into_iter();
+                    stack.push((child, grandchildren));
+                }
+                None => {
+                    let (node, _) = stack.pop().unwrap();
+                    visitor.leave(&node);
+                }
+            }
+        }
+    }
+
+    /// Walks down from this node along the given path of keys, for as long as
+    /// the children exist.
+    ///
+    /// Returns the last node found, the number of keys matched and whether
+    /// all keys were matched.
+    fn walk_path<I>(&self, keys: I) -> (Node<K, V, Logger>, usize, bool)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        // Like in `Node::ensure_path`, the locked children of the parent of
+        // `node` guarantee that `node` cannot be freed while we look at it.
+        let mut parent_children = None::<RwLockReadGuard<Map<K, UnsafeNode<K, V, Logger>>>>;
+        let mut node = unsafe { UnsafeNode::clone(self.as_unsafe_node()) };
+        let mut matched = 0;
+        let mut keys = keys.into_iter();
+        let complete = loop {
+            let key = match keys.next() {
+                Some(key) => key,
+                None => break true,
+            };
+            let this = unsafe { &*(&*node as *const NodeInner<K, V, Logger>) };
+            let children = this.children.read();
+            let child = match children.get(key.borrow(), |node| node.key()) {
+                Some(child) => unsafe { UnsafeNode::clone(child) },
+                None => break false,
+            };
+            parent_children = Some(children);
+            node = child;
+            matched += 1;
+        };
+        let node = if parent_children.is_some() {
+            unsafe { UnsafeNode::look_up_from_children(&node) }
+        } else {
+            self.clone()
+        };
+        (node, matched, complete)
+    }
+
+    /// Returns a reference to the value this node was created with.
+    #[inline]
+    pub fn value(&self) -> &V {
+        &self.as_unsafe_node().value
+    }
+
+    /// Returns whether this node is the root of its tree.
+    #[inline]
+    pub fn is_root(&self) -> bool {
+        self.as_unsafe_node().root().is_none()
+    }
+
+    /// Pins this node and its ancestors, making them immortal.
+    ///
+    /// References to pinned nodes are created and dropped without touching
+    /// their refcount, and pinned nodes are never put on the free list of the
+    /// tree nor freed, not even when the tree is dropped, which thus leaks
+    /// them and their values, along with the root of the tree and the state
+    /// shared by its nodes, see `Tree::has_pinned`.
+    pub fn pin(&self) {
+        // The unpinned ancestors are pinned from the root down, so that the
+        // ancestors of a pinned node are always pinned.
+        let mut unpinned = vec![];
+        let mut node = Some(self.as_unsafe_node());
+        while let Some(this) = node {
+            if this.pinned.load(Ordering::Relaxed) {
+                break;
+            }
+            unpinned.push(this);
+            node = this.parent();
+        }
+        for this in unpinned.into_iter().rev() {
+            // The refcount of the node cannot reach 0 while we are pinning
+            // it, given we hold a reference to it or to one of its
+            // descendants.
+            if !this.pinned.swap(true, Ordering::Relaxed) {
+                this.refcount.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns whether this node is pinned, see `Node::pin`.
+    #[inline]
+    pub fn is_pinned(&self) -> bool {
+        self.as_unsafe_node().pinned.load(Ordering::Relaxed)
+    }
+
+    /// Returns the parent of this node, or `None` if this node is the root.
+    pub fn parent(&self) -> Option<Node<K, V, Logger>> {
+        let parent = self.as_unsafe_node().parent()?;
+        // The parent is kept alive by the reference this node holds to it.
+        Some(unsafe { UnsafeNode::to_node(parent) })
+    }
+
+    /// Returns the root of the tree this node belongs to.
+    ///
+    /// If this node is the root itself, this just returns a new reference
+    /// to it.
+    pub fn root(&self) -> Node<K, V, Logger> {
+        let this = self.as_unsafe_node();
+        // The root is kept alive by the chain of references from this node
+        // to its parent, up to the root.
+        unsafe { UnsafeNode::to_node(this.root().unwrap_or(this)) }
+    }
+
+    /// Returns a borrowed reference to this node, which doesn't touch its
+    /// refcount.
+    pub fn as_node_ref(&self) -> NodeRef<'_, K, V, Logger> {
+        unsafe { NodeRef::new(self.as_unsafe_node()) }
+    }
+
+    /// Creates a new weak reference to this node.
+    pub fn downgrade(&self) -> WeakNode<K, V, Logger> {
+        let this = self.as_unsafe_node();
+        let mut weak_cell = this.weak_cell.load(Ordering::Acquire);
+        if weak_cell.is_null() {
+            let new_cell = Arc::new(Mutex::new(Some(unsafe { UnsafeNode::clone(this) })));
+            let new_cell = Arc::into_raw(new_cell) as *mut WeakCell<K, V, Logger>;
+            match this.weak_cell.compare_exchange(
+                ptr::null_mut(),
+                new_cell,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => weak_cell = new_cell,
+                Err(current_cell) => {
+                    // Another thread created the cell first.
+                    drop(unsafe { Arc::from_raw(new_cell) });
+                    weak_cell = current_cell;
+                }
+            }
+        }
+        // The cell is kept alive by this node, which cannot be freed while we
+        // hold a reference to it.
+        let weak_cell = ManuallyDrop::new(unsafe { Arc::from_raw(weak_cell) });
+        WeakNode::new(Arc::clone(&weak_cell))
+    }
+
+    /// Returns an iterator over this node and its ancestors, starting with
+    /// this node itself and ending with the root.
+    pub fn ancestors(&self) -> AncestorNodes<K, V, Logger> {
+        AncestorNodes::new(self.clone())
+    }
+
+    /// Returns an iterator over the values of this node and its ancestors,
+    /// starting with this node's own value and ending with the root value.
+    ///
+    /// Unlike `Node::ancestors`, this doesn't touch the refcount of any node.
+    pub fn ancestor_values(&self) -> AncestorValues<'_, K, V, Logger> {
+        AncestorValues::new(self.as_unsafe_node())
+    }
+
+    /// Returns the values from the root down to this node, both included.
+    pub fn path_from_root(&self) -> Vec<&V> {
+        let mut path = self.ancestor_values().collect::<Vec<_>>();
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(feature = "collector")]
+impl<K, V, Logger> Node<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Returns the current statistics of the tree of this root node, or
+    /// `None` if the tree was dropped.
+    pub(crate) fn tree_gc_stats(&self) -> Option<GcStats> {
+        let root = self.as_unsafe_node();
+        debug_assert!(root.root().is_none());
+        if root.next_free.load(Ordering::Relaxed).is_null() {
+            return None;
+        }
+        Some(root.gc_stats())
+    }
+
+    /// Runs the garbage collector of the tree of this root node, which does
+    /// nothing if the tree was dropped, unless another collection is already
+    /// ongoing.
+    pub(crate) fn tree_try_gc(&self, min_age: usize) -> Option<GcReport> {
+        let root = self.as_unsafe_node();
+        debug_assert!(root.root().is_none());
+        let _collecting = root.state().try_start_collecting()?;
+        Some(unsafe { UnsafeNode::swap_free_list_and_gc(root, NodeInner::DANGLING_PTR, min_age) })
+    }
+}
+
+impl<'a, K, V, Logger> NodeRef<'a, K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'b> &'b V: Into<K>,
+    Logger: Log,
+{
+    /// Returns a reference to the value of this node.
+    pub fn value(&self) -> &'a V {
+        &self.as_unsafe_node().value
+    }
+
+    /// Returns whether this node is the root of its tree.
+    #[inline]
+    pub fn is_root(&self) -> bool {
+        self.as_unsafe_node().root().is_none()
+    }
+
+    /// Returns the parent of this node, or `None` if this node is the root.
+    ///
+    /// Like this node, its parent is kept alive by the node or tree this
+    /// reference borrows from.
+    pub fn parent(&self) -> Option<NodeRef<'a, K, V, Logger>> {
+        let parent = self.as_unsafe_node().parent()?;
+        Some(unsafe { NodeRef::new(parent) })
+    }
+
+    /// Returns the root of the tree this node belongs to.
+    pub fn root(&self) -> NodeRef<'a, K, V, Logger> {
+        let this = self.as_unsafe_node();
+        unsafe { NodeRef::new(this.root().unwrap_or(this)) }
+    }
+
+    /// Calls `f` with a borrowed reference to the child of this node with the
+    /// given key, if any, and returns its result.
+    ///
+    /// The child is kept alive by read-locking the children of this node
+    /// until `f` returns, instead of by touching its refcount. Anything that
+    /// needs to write-lock them, such as creating a child in this node or
+    /// freeing one of its children, blocks until then, and deadlocks if done
+    /// from `f` itself. `NodeRef::to_node` creates a reference to the child
+    /// which outlives `f`.
+    pub fn with_child<R>(
+        &self,
+        key: &K,
+        f: impl FnOnce(NodeRef<'_, K, V, Logger>) -> R,
+    ) -> Option<R> {
+        // Recursive read locks let `f` look up children of this node again,
+        // even if a writer is waiting.
+        let children = self.as_unsafe_node().children.read_recursive();
+        let child = children.get(key, |node| node.key())?;
+        Some(f(unsafe { NodeRef::new(child) }))
+    }
+
+    /// Returns an iterator over the values of this node and its ancestors,
+    /// starting with this node's own value and ending with the root value.
+    pub fn ancestor_values(&self) -> AncestorValues<'a, K, V, Logger> {
+        AncestorValues::new(self.as_unsafe_node())
+    }
+
+    /// Creates a new reference to this node.
+    ///
+    /// This counts as looking the node up again if it is waiting on the free
+    /// list of the tree, see `TreeBuilder::free_list_capacity`.
+    pub fn to_node(&self) -> Node<K, V, Logger> {
+        // The node is kept alive by the node or tree this reference borrows
+        // from, or by the locked children of its parent.
+        let node = unsafe { UnsafeNode::to_node(self.as_unsafe_node()) };
+        UnsafeNode::on_lookup(node.as_unsafe_node());
+        node
+    }
+}
+
+impl<K, V, Logger> WeakNode<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Attempts to create a new reference to the node, which fails if the
+    /// node was freed.
+    ///
+    /// This succeeds as long as the node exists, including when it is
+    /// waiting on the free list, in which case it is resurrected.
+    pub fn upgrade(&self) -> Option<Node<K, V, Logger>> {
+        // The cell is emptied while it is locked before the node is freed.
+        let node = unsafe { UnsafeNode::upgrade(self.as_cell().lock().as_ref()?)? };
+        UnsafeNode::on_lookup(node.as_unsafe_node());
+        Some(node)
+    }
+}
+
+impl<K, V, Logger> Clone for Node<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    #[inline]
+    fn clone(&self) -> Self {
+        unsafe { UnsafeNode::to_node(self.as_unsafe_node()) }
+    }
+}
+
+impl<K, V, Logger> UnsafeNode<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Creates a new node from this unsafe node, incrementing its refcount.
+    ///
+    /// # Safety
+    ///
+    /// The refcount of this unsafe node should be guaranteed to be greater
+    /// than 0 for the duration of the call, e.g. because the caller holds
+    /// a node referencing it.
+    #[inline]
+    unsafe fn to_node(this: &Self) -> Node<K, V, Logger> {
+        if !this.pinned.load(Ordering::Relaxed) {
+            this.refcount.fetch_add(1, Ordering::Relaxed);
+        }
+        Node::from_unsafe_node(UnsafeNode::clone(this))
+    }
+
+    /// Creates a new node from this unsafe node, which was found in the
+    /// children of its parent.
+    ///
+    /// # Safety
+    ///
+    /// The children of this unsafe node's parent should be locked, for
+    /// reading or for writing, for the duration of the call.
+    unsafe fn to_node_from_children(this: &Self) -> Node<K, V, Logger> {
+        // Another thread may currently be trying to release the last
+        // reference to this node, which can only be done while write-locking
+        // the children of its parent, so it will notice the refcount we
+        // increment here and leave the node alone. See `UnsafeNode::release`.
+        if !this.pinned.load(Ordering::Relaxed) {
+            let old_refcount = this.refcount.fetch_add(1, Ordering::Relaxed);
+            debug_assert!(old_refcount != 0);
+        }
+        Node::from_unsafe_node(UnsafeNode::clone(this))
+    }
+
+    /// Creates a new node from this unsafe node, which was looked up by the
+    /// caller in the children of its parent, see `UnsafeNode::on_lookup`.
+    ///
+    /// # Safety
+    ///
+    /// The children of this unsafe node's parent should be locked, for
+    /// reading or for writing, for the duration of the call.
+    unsafe fn look_up_from_children(this: &Self) -> Node<K, V, Logger> {
+        let node = UnsafeNode::to_node_from_children(this);
+        UnsafeNode::on_lookup(this);
+        node
+    }
+
+    /// Records that this node was looked up again if it is waiting on the
+    /// free list of its tree, so that releasing a reference to it then counts
+    /// as a new use of it, see `UnsafeNode::touch_free_list`.
+    ///
+    /// References created by traversing the tree, or to navigate to parents,
+    /// don't go through this, so that they leave the free list alone.
+    fn on_lookup(this: &Self) {
+        if !this.next_free.load(Ordering::Relaxed).is_null() {
+            this.looked_up.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Accounts for the creation of this new non-root node.
+    ///
+    /// This is called with the children of its parent locked, and with the
+    /// key index of the tree if it was already locked by the caller, see
+    /// `TreeState::try_lock_key_index`.
+    fn on_new_child(this: &Self, key_index: Option<KeyIndexGuard<'_, K, V, Logger>>) {
+        let state = this.root().unwrap().state();
+        state.node_count.fetch_add(1, Ordering::Relaxed);
+        if let Some(mut key_index) = key_index.or_else(|| state.lock_key_index()) {
+            key_index.entry(this.key()).or_default().push(unsafe { UnsafeNode::clone(this) });
+        }
+        Logger::log_new(UnsafeNode::as_ptr(this) as *const c_void);
+    }
+
+    /// Removes this node from the given children of its parent, if it is
+    /// still there. Returns whether it was.
+    fn remove_from_children(this: &Self, children: &mut Map<K, UnsafeNode<K, V, Logger>>) -> bool {
+        let key = this.key();
+        match children.get(&key, |node| node.key()) {
+            Some(child) if ptr::eq::<NodeInner<K, V, Logger>>(&**child, &**this) => {
+                children.remove(&key, |node| node.key());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns new references to the descendants of this node with the given
+    /// key, without touching the refcount of the other ones.
+    fn descendants_with_key(this: &Self, key: &K) -> Vec<Node<K, V, Logger>> {
+        let mut nodes = vec![];
+        // The read-locked children of each node on the path from this node
+        // down to the node being visited, with the ones left to visit. The
+        // children of a node cannot be freed while they are locked, and
+        // their own children keep them alive, so the pointers stay valid
+        // until they are popped.
+        let mut stack = vec![];
+        let children = this.children.read();
+        let pending = children.values().map(|child| child as *const Self).collect::<Vec<_>>();
+        stack.push((children, pending));
+        while let Some((_, pending)) = stack.last_mut() {
+            let child = match pending.pop() {
+                Some(child) => unsafe { &*child },
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            if child.key() == *key {
+                nodes.push(unsafe { UnsafeNode::to_node_from_children(child) });
+            }
+            let children = child.children.read();
+            let pending = children.values().map(|child| child as *const Self).collect::<Vec<_>>();
+            stack.push((children, pending));
+        }
+        nodes
+    }
+
+    /// Removes this node from the key index of its tree, if it is still
+    /// there.
+    ///
+    /// This is called with the children of its parent locked.
+    fn unregister_key(key_index: &KeyIndex<K, V, Logger>, this: &Self, key: K) {
+        let mut key_index = key_index.lock();
+        if let Entry::Occupied(mut entry) = key_index.entry(key) {
+            let nodes = entry.get_mut();
+            if let Some(i) = nodes
+                .iter()
+                .position(|node| ptr::eq::<NodeInner<K, V, Logger>>(&**node, &**this))
+            {
+                nodes.swap_remove(i);
+                if nodes.is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+    }
+
+    /// Creates a new node from this unsafe node, unless its refcount already
+    /// reached 0, in which case it is being freed by another thread.
+    ///
+    /// # Safety
+    ///
+    /// This unsafe node should not have been freed yet.
+    unsafe fn upgrade(this: &Self) -> Option<Node<K, V, Logger>> {
+        let mut refcount = this.refcount.load(Ordering::Relaxed);
+        loop {
+            if refcount == 0 {
+                return None;
+            }
+            match this.refcount.compare_exchange_weak(
+                refcount,
+                refcount + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(Node::from_unsafe_node(UnsafeNode::clone(this))),
+                Err(current) => refcount = current,
+            }
+        }
+    }
+
+    /// Releases a reference to this node, unless it is its last one. Returns
+    /// whether the reference was released.
+    fn release_unless_last(this: &Self) -> bool {
+        let mut refcount = this.refcount.load(Ordering::Relaxed);
+        while refcount != 1 {
+            match this.refcount.compare_exchange_weak(
+                refcount,
+                refcount - 1,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => refcount = current,
+            }
+        }
+        false
+    }
 
-impl<K, V> Tree<K, V>
-where
-    K: Eq + Hash,
-    for<'a> &'a V: Into<K>,
-{
-    /// Creates a new tree from a root value.
+    /// Releases a reference to this node without going through the free list,
+    /// freeing the node if that was its last reference, and then its ancestors
+    /// whose last reference was held by the child freed before them.
     ///
-    /// Note that the root value is never going to be accessed by either the
-    /// crate or the caller.
-    pub fn new(root: V) -> Self {
-        Self::with_logger(root)
+    /// The refcount of a non-root node only ever reaches 0 while the children
+    /// of its parent are write-locked, and the node is removed from them
+    /// before the lock is released, so no other thread can find it in the
+    /// meantime to resurrect it.
+    ///
+    /// Returns the number of nodes that were actually freed.
+    ///
+    /// # Safety
+    ///
+    /// The caller should own the reference it is releasing.
+    unsafe fn release(this: &Self) -> usize {
+        let mut this = UnsafeNode::clone(this);
+        let mut freed = 0;
+        loop {
+            if this.pinned.load(Ordering::Relaxed) {
+                // Pinned nodes are never freed, and neither are their
+                // ancestors.
+                return freed;
+            }
+            if UnsafeNode::release_unless_last(&this) {
+                // This wasn't the last reference to this node, nothing to do
+                // anymore.
+                return freed;
+            }
+            if let Some(parent) = this.parent() {
+                let mut children = parent.children.write();
+                // Another thread may have resurrected this node while we
+                // were trying to lock the children of its parent, leave it
+                // alone.
+                if this.refcount.fetch_sub(1, Ordering::Release) != 1 {
+                    return freed;
+                }
+                // The node may have been detached from its parent by
+                // `Tree::invalidate`, in which case another node may have
+                // taken its place in the children since then.
+                UnsafeNode::remove_from_children(&this, &mut children);
+                if let Some(key_index) = &this.root().unwrap().state().key_index {
+                    UnsafeNode::unregister_key(key_index, &this, this.key());
+                }
+            } else if this.refcount.fetch_sub(1, Ordering::Release) != 1 {
+                return freed;
+            }
+            let parent = UnsafeNode::free(this);
+            freed += 1;
+            match parent {
+                // The node had a parent, we reiterate the loop to release
+                // the reference the node held to it.
+                Some(parent) => this = parent.into_unsafe_node(),
+                // The node was the root, we don't have anything to do
+                // anymore.
+                None => return freed,
+            }
+        }
     }
-}
 
-impl<K, V, Logger> Tree<K, V, Logger>
-where
-    K: Eq + Hash,
-    for<'a> &'a V: Into<K>,
-    Logger: Log,
-{
-    /// Creates a new tree from a root value.
+    /// Frees this node, whose refcount just reached 0 and which was already
+    /// removed from the children of its parent, if any.
     ///
-    /// Note that the root value is never going to be accessed by either the
-    /// crate or the caller.
-    pub fn with_logger(root: V) -> Self {
-        let tree = unsafe {
-            Tree::from_root_node(Node::from_unsafe_node(UnsafeNode::new(NodeInner {
-                value: root,
-                marker,
-                ancestors: None,
-                children: Default::default(),
-                refcount: AtomicUsize::new(1),
-                next_free: AtomicPtr::new(NodeInner::DANGLING_PTR),
-                free_count: Default::default(),
-            })))
-        };
-        Logger::log_new(&**tree.as_unsafe_node() as *const NodeInner<K, V, Logger> as *const c_void);
-        tree
+    /// Returns the reference the node held on its parent, if any.
+    ///
+    /// # Safety
+    ///
+    /// No other thread should be able to find this node anymore.
+    unsafe fn free(mut this: Self) -> Option<Node<K, V, Logger>> {
+        atomic::fence(Ordering::Acquire);
+        debug_assert!(this.next_free.load(Ordering::Relaxed).is_null());
+        // Weak references to this node must not be able to upgrade it
+        // anymore once it is freed.
+        let weak_cell = this.weak_cell.load(Ordering::Relaxed);
+        if !weak_cell.is_null() {
+            *Arc::from_raw(weak_cell).lock() = None;
+        }
+        if let Some(root) = this.root() {
+            root.state().node_count.fetch_sub(1, Ordering::Relaxed);
+        }
+        // Remove the parent reference from the child to avoid recursively
+        // dropping it.
+        let parent = UnsafeNode::deref_mut(&mut this).ancestors.take().map(Ancestors::into_parent);
+        Logger::log_drop(UnsafeNode::as_ptr(&this) as *const c_void);
+        UnsafeNode::drop(&mut this);
+        parent
     }
-}
 
-impl<K, V, Logger> Tree<K, V, Logger>
-where
-    K: Eq + Hash,
-    for<'a> &'a V: Into<K>,
-    Logger: Log,
-{
-    /// Runs the garbage collector of the tree's free list if needed according
-    /// to some heuristics.
-    pub fn maybe_gc(&self) {
-        if self.as_unsafe_node().free_count.load(Ordering::Relaxed) > GC_COUNT_THRESHOLD {
-            unsafe { self.swap_free_list_and_gc(NodeInner::DANGLING_PTR) }
+    /// Releases a reference to this non-root node, transferring it to the
+    /// free list of its tree if it was its last one, or releasing it for
+    /// good if the free list is gone.
+    ///
+    /// If the node was pushed on a bounded free list, this returns a new
+    /// reference to the root of the tree to trim the free list with, given
+    /// the node may be freed as soon as it is pushed, along with its
+    /// ancestors if the tree is concurrently dropped.
+    ///
+    /// # Safety
+    ///
+    /// The caller should own the reference it is releasing, and this node
+    /// should not be pinned.
+    unsafe fn release_to_free_list(this: &Self) -> Option<Node<K, V, Logger>> {
+        let root = this.root().unwrap();
+        let bounded = root.state().free_capacity.is_some();
+        if this.looked_up.load(Ordering::Relaxed) && this.looked_up.swap(false, Ordering::Relaxed)
+        {
+            UnsafeNode::touch_free_list(this);
+        }
+        if UnsafeNode::release_unless_last(this) {
+            // This wasn't the last reference to this node, nothing to do
+            // anymore.
+            return None;
         }
+        // This is the last reference to this node, which is therefore not
+        // on the free list, and we transfer it to the free list instead of
+        // releasing it. The refcount of the node never reaches 0 in the
+        // process, so nothing can free it from under our feet, even if
+        // another thread resurrects it and it gets collected before we are
+        // done.
+        let root = if bounded { Some(UnsafeNode::to_node(root)) } else { None };
+        if UnsafeNode::push_on_free_list(this) {
+            return root;
+        }
+        UnsafeNode::release(this);
+        None
     }
 
-    /// Runs the garbage collector of the tree's free list.
-    pub fn gc(&self) {
-        unsafe { self.swap_free_list_and_gc(NodeInner::DANGLING_PTR) }
+    /// Swaps the free list's head of this root with a given pointer and
+    /// collects the free list.
+    ///
+    /// Unused nodes which survived less than `min_age` garbage collections
+    /// are requeued, see `UnsafeNode::collect_free_chain`.
+    ///
+    /// Returns a report of what the garbage collector did, which is empty if
+    /// the free list is gone already.
+    ///
+    /// # Safety
+    ///
+    /// This node should be a root.
+    unsafe fn swap_free_list_and_gc(
+        root: &Self,
+        ptr: *mut NodeInner<K, V, Logger>,
+        min_age: usize,
+    ) -> GcReport {
+        let start = Instant::now();
+        let mut report = match UnsafeNode::swap_free_list(root, ptr) {
+            Some(head) => UnsafeNode::collect_free_chain(root, head, min_age),
+            None => GcReport::default(),
+        };
+        report.elapsed = start.elapsed();
+        report
     }
 
-    /// Swaps the tree's free list's head with a given pointer and collects
-    /// the free list, taking care of not swapping any pointer with its lowest
-    /// bit set, given that would break the lock currently held by another
-    /// thread.
-    unsafe fn swap_free_list_and_gc(&self, ptr: *mut NodeInner<K, V, Logger>) {
-        let root = self.as_unsafe_node();
+    /// Swaps the free list's head of this root with a given pointer, taking
+    /// care of not swapping any pointer with its lowest bit set, given that
+    /// would break the lock currently held by another thread.
+    ///
+    /// Returns the detached free list, or `None` if it was empty or gone
+    /// already.
+    ///
+    /// # Safety
+    ///
+    /// This node should be a root.
+    unsafe fn swap_free_list(
+        root: &Self,
+        ptr: *mut NodeInner<K, V, Logger>,
+    ) -> Option<*mut NodeInner<K, V, Logger>> {
+        if root.state().free_capacity.is_some() {
+            // The nodes of a bounded free list must not look like they are
+            // still on it once detached, so the free list needs to be locked
+            // while their previous pointers are reset.
+            let head = UnsafeNode::lock_free_list(root)?;
+            let mut node = head;
+            while node != NodeInner::DANGLING_PTR {
+                (*node).prev_free.store(ptr::null_mut(), Ordering::Relaxed);
+                node = (*node).next_free.load(Ordering::Relaxed);
+            }
+            root.state().free_tail.store(ptr::null_mut(), Ordering::Relaxed);
+            root.next_free.store(ptr, Ordering::Release);
+            return if head == NodeInner::DANGLING_PTR { None } else { Some(head) };
+        }
         let mut head = root.next_free.load(Ordering::Relaxed);
         loop {
-            // This is only ever called when the tree is dropped or when
-            // a GC is manually requested, so the free list's head should
-            // never be null already.
-            debug_assert!(!head.is_null());
-            if head == ptr {
+            if head == ptr || head.is_null() {
                 // In the case of swapping the pointer with
                 // `NodeInner::DANGLING_PTR`, we can return immediately
                 // because the free list is already empty so there is nothing
-                // to GC.
-                return;
+                // to GC. The free list can also be gone already if the tree
+                // was dropped while a collector was still running.
+                debug_assert!(!ptr.is_null());
+                return None;
             }
             // Unmask the lock bit from the current head, this is the most
             // probable value `compare_exchange_weak` will read when the other
             // thread currently locking the free list unlocks it.
             head = (head as usize & !1) as *mut NodeInner<K, V, Logger>;
-            // This could fail if the free list head is
-            // `NodeInner::DANGLING_PTR` with the lowest bit set, which
-            // makes no sense.
-            debug_assert!(head != ptr);
+            // The head may be `NodeInner::DANGLING_PTR` if the free list is
+            // empty but currently locked, in which case we may end up
+            // detaching an empty free list, which is harmless.
             match root.next_free.compare_exchange_weak(
                 head,
                 ptr,
                 Ordering::AcqRel,
                 Ordering::Relaxed,
             ) {
-                Ok(old_head) => {
-                    head = old_head;
-                    break;
-                }
+                Ok(old_head) => return Some(old_head),
                 Err(current_head) => head = current_head,
             }
         }
-        loop {
-            if head == NodeInner::DANGLING_PTR {
-                // We reached the end of the free list.
-                return;
-            }
+    }
+
+    /// Collects a chain of nodes detached from the free list of this root, up
+    /// to `NodeInner::DANGLING_PTR`.
+    ///
+    /// Unused nodes which survived less than `min_age` garbage collections
+    /// are not freed but aged and requeued at the head of the free list, in
+    /// the same order.
+    ///
+    /// Returns a report of what the garbage collector did, without its
+    /// elapsed time.
+    ///
+    /// # Safety
+    ///
+    /// The chain should have been detached from the free list of this root by
+    /// the caller, which then owns the references held by the free list on
+    /// its nodes.
+    unsafe fn collect_free_chain(
+        root: &Self,
+        mut head: *mut NodeInner<K, V, Logger>,
+        min_age: usize,
+    ) -> GcReport {
+        let mut report = GcReport::default();
+        let mut requeued = FreeChain::new();
+        while head != NodeInner::DANGLING_PTR {
             let node = UnsafeNode::from_raw(head);
             let next = node.next_free.swap(ptr::null_mut(), Ordering::Relaxed);
             // This fails if we found a node on the free list with a next
@@ -159,148 +1525,221 @@ where
             // It wouldn't make sense for a node on the free list to have
             // a null next free pointer.
             debug_assert!(!head.is_null());
-            drop(Node::from_unsafe_node(node));
+            report.processed += 1;
+            if node.gc_age.load(Ordering::Relaxed) < min_age {
+                // The node is too young to be freed. If it is still unused,
+                // we age it and requeue it, otherwise it was resurrected and
+                // we just release the reference held by the free list.
+                if UnsafeNode::release_unless_last(&node) {
+                    root.state().free_count.fetch_sub(1, Ordering::Relaxed);
+                    report.resurrected += 1;
+                } else {
+                    node.gc_age.fetch_add(1, Ordering::Relaxed);
+                    requeued.push_back(head);
+                    report.requeued += 1;
+                }
+                head = next;
+                continue;
+            }
+            // The node isn't waiting on the free list anymore, even if it
+            // ends up not being freed because it was resurrected.
+            root.state().free_count.fetch_sub(1, Ordering::Relaxed);
+            // We release the reference held by the free list ourselves instead
+            // of dropping a `Node`, otherwise the node would just be pushed
+            // back on the free list if we were its last reference.
+            let freed = UnsafeNode::release(&node);
+            report.freed += freed;
+            if freed == 0 {
+                // Another thread resurrected the node in the meantime.
+                report.resurrected += 1;
+            }
             // Iterates on the next item in the free list.
             head = next;
         }
+        requeued.requeue(root, &mut report);
+        report
     }
-}
-
-impl<K, V, Logger> Drop for Tree<K, V, Logger>
-where
-    K: Eq + Hash,
-    for<'a> &'a V: Into<K>,
-    Logger: Log,
-{
-    fn drop(&mut self) {
-        unsafe { self.swap_free_list_and_gc(ptr::null_mut()) }
-    }
-}
 
-impl<K, V, Logger> Node<K, V, Logger>
-where
-    K: Eq + Hash,
-    for<'a> &'a V: Into<K>,
-    Logger: Log,
-{
-    /// Ensures that a child exists in this node with the given value.
+    /// Locks the free list of this root, returning its current head, or `None`
+    /// if the free list was destroyed.
     ///
-    /// If a child with this value was already created since last GC happened,
-    /// that child is returned instead.
-    pub fn ensure_child(&self, value: V) -> Node<K, V, Logger> {
-        let this = self.as_unsafe_node();
-        let children = this.children.upgradable_read();
-        let key = (&value).into();
-        if let Some(child) = children.get(&key, |node| node.key()) {
-            if child.refcount.fetch_add(1, Ordering::Relaxed) == 0 {
-                // Consider a node with a refcount of 1 being dropped on a different
-                // thread A while not already on the free list. A decrements the
-                // refcount to 0 while we increment it back to 1 for the upgrade.
-                //
-                // Now, if the newly upgraded node value is dropped, its refcount
-                // again reaches 0 again and the node is put on the free list.
-                //
-                // If a GC is then triggered before the destructor on thread A
-                // finishes executing, we have a use after free vulnerability.
-                //
-                // To avoid that, we try to push the child on the free list
-                // ourselves.
-                unsafe {
-                    UnsafeNode::push_on_free_list(child);
-                }
+    /// The free list must then be unlocked by storing its new head with
+    /// `Ordering::Release`, which must not have its lowest bit set.
+    fn lock_free_list(root: &Self) -> Option<*mut NodeInner<K, V, Logger>> {
+        let mut head = root.next_free.load(Ordering::Relaxed);
+        loop {
+            if head.is_null() {
+                return None;
+            }
+            // Unmask the lock bit from the current head, this is the most
+            // probable value `compare_exchange_weak` will read when the other
+            // thread currently locking the free list unlocks it.
+            head = (head as usize & !1) as *mut NodeInner<K, V, Logger>;
+            let head_lock = (head as usize | 1) as *mut NodeInner<K, V, Logger>;
+            match root.next_free.compare_exchange_weak(
+                head,
+                head_lock,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(head),
+                Err(current_head) => head = current_head,
             }
-            return unsafe { Node::from_unsafe_node(UnsafeNode::clone(child)) };
         }
-        let mut children = RwLockUpgradableReadGuard::upgrade(children);
-        let unsafe_node = children.get_or_insert_with(
-            key,
-            |node| node.key(),
-            || {
-                let root = unsafe { UnsafeNode::clone(this.root().unwrap()) };
-                UnsafeNode::new(NodeInner {
-                    value,
-                    marker,
-                    ancestors: Some(unsafe { Ancestors::new(root, self.clone()) }),
-                    children: Default::default(),
-                    refcount: AtomicUsize::new(1),
-                    next_free: Default::default(),
-                    free_count: Default::default(),
-                })
-            },
-        );
-        let node = unsafe { Node::from_unsafe_node(UnsafeNode::clone(unsafe_node)) };
-        Logger::log_new(&**node.as_unsafe_node() as *const NodeInner<K, V, Logger> as *const c_void);
-        node
     }
-}
-
-impl<K, V, Logger> Clone for Node<K, V, Logger>
-where
-    K: Eq + Hash,
-    for<'a> &'a V: Into<K>,
-    Logger: Log,
-{
-    #[inline]
-    fn clone(&self) -> Self {
-        let this = self.as_unsafe_node();
-        this.refcount.fetch_add(1, Ordering::Relaxed);
-        unsafe { Node::from_unsafe_node(UnsafeNode::clone(this)) }
-    }
-}
 
-impl<K, V, Logger> UnsafeNode<K, V, Logger>
-where
-    K: Eq + Hash,
-    for<'a> &'a V: Into<K>,
-    Logger: Log,
-{
-    unsafe fn drop_without_free_list(this: &mut Self) {
-        let mut this = UnsafeNode::clone(this);
-        loop {
-            if let Some(parent) = this.ancestors.as_ref().map(Ancestors::parent) {
-                let mut children = parent.children.write();
-                // Another thread may have resurrected this node while
-                // we were trying to drop it, leave it alone. The
-                // operation can be relaxed because we are currently
-                // write-locking its parent children list and
-                // resurrection is only done from `Node::ensure_child`
-                // which read-locks that same children list.
-                if this.refcount.load(Ordering::Relaxed) != 0 {
+    /// Evicts the least recently released nodes from the bounded free list
+    /// of this root until it holds no more nodes than its capacity.
+    ///
+    /// Evicting a node requires write-locking the children of its parent, so
+    /// this stops early instead of waiting if they are locked already, e.g.
+    /// by the caller. The free list is then trimmed the next time a node is
+    /// pushed on it.
+    ///
+    /// # Safety
+    ///
+    /// This node should be a root.
+    unsafe fn trim_free_list(root: &Self) {
+        let state = root.state();
+        let capacity = match state.free_capacity {
+            Some(capacity) => capacity,
+            None => return,
+        };
+        while state.free_count.load(Ordering::Relaxed) > capacity {
+            let head = match UnsafeNode::lock_free_list(root) {
+                Some(head) => head,
+                None => return,
+            };
+            let tail = state.free_tail.load(Ordering::Relaxed);
+            if tail.is_null() {
+                // The free list was detached by the garbage collector, which
+                // didn't account for it yet.
+                root.next_free.store(head, Ordering::Release);
+                return;
+            }
+            // The tail is kept alive by the reference held on it by the free
+            // list, and its parent by the reference held on it by the tail.
+            let node = UnsafeNode::from_raw(tail);
+            let mut children = match node.parent().unwrap().children.try_write() {
+                Some(children) => children,
+                None => {
+                    root.next_free.store(head, Ordering::Release);
                     return;
                 }
-                this.next_free.store(ptr::null_mut(), Ordering::Relaxed);
-                children.remove(&this.key(), |node| node.key());
-            }
-            atomic::fence(Ordering::Acquire);
-            debug_assert_eq!(this.refcount.load(Ordering::Relaxed), 0);
-            debug_assert!(this.next_free.load(Ordering::Relaxed).is_null());
-            // Remove the parent reference from the child to avoid
-            // recursively dropping it.
-            let parent = {
-                UnsafeNode::deref_mut(&mut this)
-                    .ancestors
-                    .take()
-                    .map(Ancestors::into_parent)
             };
-            Logger::log_drop(&*this as *const NodeInner<K, V, Logger> as *const c_void);
-            UnsafeNode::drop(&mut this);
-            if let Some(parent) = parent {
-                this = parent.into_unsafe_node();
-                if this.refcount.fetch_sub(1, Ordering::Release) == 1 {
-                    // The node had a parent and its refcount reached
-                    // zero, we reiterate the loop to drop it too.
-                    continue;
-                }
+            let prev = node.prev_free.swap(ptr::null_mut(), Ordering::Relaxed);
+            node.next_free.store(ptr::null_mut(), Ordering::Relaxed);
+            UnsafeNode::set_prev_free(root, NodeInner::DANGLING_PTR, prev);
+            if prev == NodeInner::DANGLING_PTR {
+                root.next_free.store(NodeInner::DANGLING_PTR, Ordering::Release);
+            } else {
+                (*prev).next_free.store(NodeInner::DANGLING_PTR, Ordering::Relaxed);
+                root.next_free.store(head, Ordering::Release);
             }
-            // The node didn't have a parent or the parent has other
-            // live reference elsewhere, we don't have anything to do
-            // anymore.
+            state.free_count.fetch_sub(1, Ordering::Relaxed);
+            // We now own the reference held on the node by the free list. The
+            // node may have been resurrected in the meantime, in which case
+            // it goes back on the free list once unused, see
+            // `UnsafeNode::release`.
+            if node.refcount.fetch_sub(1, Ordering::Release) != 1 {
+                continue;
+            }
+            UnsafeNode::remove_from_children(&node, &mut children);
+            if let Some(key_index) = &state.key_index {
+                UnsafeNode::unregister_key(key_index, &node, node.key());
+            }
+            drop(children);
+            // The reference held by the node on its parent is released as if
+            // dropped, except that the free list is trimmed by this loop
+            // instead of recursively.
+            let parent = UnsafeNode::free(node).unwrap().into_unsafe_node();
+            if parent.pinned.load(Ordering::Relaxed) {
+                continue;
+            }
+            if parent.root().is_some() {
+                UnsafeNode::release_to_free_list(&parent);
+            } else {
+                UnsafeNode::release(&parent);
+            }
+        }
+    }
+
+    /// Resets the age of this node if it is waiting on the free list of its
+    /// tree, given a reference to it is being released after it was looked
+    /// up again, so that nodes which keep being reused are not freed by
+    /// generational collections.
+    ///
+    /// If the free list is bounded, the node is also moved to its head, so
+    /// that nodes are evicted in the order they were last used in.
+    ///
+    /// # Safety
+    ///
+    /// The caller should own a reference to this non-root node.
+    unsafe fn touch_free_list(this: &Self) {
+        // Most nodes aren't on the free list when released, this avoids
+        // locking it for them.
+        if this.next_free.load(Ordering::Relaxed).is_null() {
+            return;
+        }
+        let root = this.root().unwrap();
+        if root.state().free_capacity.is_none() {
+            // The garbage collector may be processing the node concurrently,
+            // in which case it either frees it anyway or requeues it with
+            // its age reset or incremented, none of which is a problem.
+            this.gc_age.store(0, Ordering::Relaxed);
             return;
         }
+        let mut head = match UnsafeNode::lock_free_list(root) {
+            Some(head) => head,
+            None => return,
+        };
+        let prev = this.prev_free.load(Ordering::Relaxed);
+        // The node isn't on the free list anymore if its previous pointer is
+        // null, even if the garbage collector didn't get to it yet.
+        if !prev.is_null() {
+            if prev != NodeInner::DANGLING_PTR {
+                let this_ptr = UnsafeNode::as_ptr(this) as *mut NodeInner<K, V, Logger>;
+                let next = this.next_free.load(Ordering::Relaxed);
+                (*prev).next_free.store(next, Ordering::Relaxed);
+                UnsafeNode::set_prev_free(root, next, prev);
+                this.next_free.store(head, Ordering::Relaxed);
+                this.prev_free.store(NodeInner::DANGLING_PTR, Ordering::Relaxed);
+                UnsafeNode::set_prev_free(root, head, this_ptr);
+                head = this_ptr;
+            }
+            this.gc_age.store(0, Ordering::Relaxed);
+        }
+        root.next_free.store(head, Ordering::Release);
     }
 
-    /// Pushes this node on the tree's free list. Returns false if the free list
-    /// is gone.
+    /// Sets the previous item of an item of the bounded free list of this
+    /// root, which is the tail of the free list if the item is
+    /// `NodeInner::DANGLING_PTR`.
+    ///
+    /// # Safety
+    ///
+    /// This node should be a root whose free list is locked by the caller.
+    unsafe fn set_prev_free(
+        root: &Self,
+        next: *mut NodeInner<K, V, Logger>,
+        prev: *mut NodeInner<K, V, Logger>,
+    ) {
+        if next == NodeInner::DANGLING_PTR {
+            let tail = if prev == NodeInner::DANGLING_PTR { ptr::null_mut() } else { prev };
+            root.state().free_tail.store(tail, Ordering::Relaxed);
+        } else {
+            (*next).prev_free.store(prev, Ordering::Relaxed);
+        }
+    }
+
+    /// Pushes this node on the tree's free list, transferring the reference
+    /// held by the caller to the free list. Returns false if the free list is
+    /// gone, in which case the caller still owns its reference.
+    ///
+    /// # Safety
+    ///
+    /// The caller should own a reference to this non-root node, and this
+    /// node shouldn't be on the free list already.
     unsafe fn push_on_free_list(this: &Self) -> bool {
         let root = this.root().unwrap();
         let mut old_head = root.next_free.load(Ordering::Relaxed);
@@ -315,12 +1754,6 @@ where
             // probable value `compare_exchange_weak` will read when the other
             // thread currently locking the free list unlocks it.
             old_head = (old_head as usize & !1) as *mut NodeInner<K, V, Logger>;
-            if old_head == this_ptr {
-                // The free list is currently locked or has finished being
-                // locked to put this very node at the head of the free list,
-                // which means we don't need to do it ourselves.
-                return true;
-            }
             match root.next_free.compare_exchange_weak(
                 old_head,
                 this_lock,
@@ -331,28 +1764,109 @@ where
                 Err(current_head) => old_head = current_head,
             }
         }
-        if !this.next_free.load(Ordering::Relaxed).is_null() {
-            // Another thread managed to resurrect this node and put it on
-            // the free list while we were still busy trying to lock the free
-            // list. That means we are done and we just need to unlock the free
-            // list with whatever head we read last.
-            root.next_free.store(old_head, Ordering::Release);
+        debug_assert!(this.next_free.load(Ordering::Relaxed).is_null());
+        this.gc_age.store(0, Ordering::Relaxed);
+        // The node may have been looked up while it was still on the free
+        // list before being collected and resurrected.
+        this.looked_up.store(false, Ordering::Relaxed);
+        if root.state().free_capacity.is_some() {
+            this.prev_free.store(NodeInner::DANGLING_PTR, Ordering::Relaxed);
+            UnsafeNode::set_prev_free(root, old_head, this_ptr);
+        }
+
+        // The free count is decremented by the GC without locking the
+        // free list, so this needs to be an atomic increment.
+        root.state().free_count.fetch_add(1, Ordering::Relaxed);
+
+        // Finally, we store the old free list head into this node's next free
+        // slot and we unlock the guard with the new head.
+        this.next_free.store(old_head, Ordering::Relaxed);
+        root.next_free.store(this_ptr, Ordering::Release);
+        true
+    }
+}
+
+impl<K, V, Logger> FreeChain<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    fn new() -> Self {
+        Self {
+            head: NodeInner::DANGLING_PTR,
+            tail: ptr::null_mut(),
+        }
+    }
+
+    /// Appends a node to this chain.
+    ///
+    /// # Safety
+    ///
+    /// The node should have been detached from the free list by the caller,
+    /// which owns the reference held by the free list on it.
+    unsafe fn push_back(&mut self, node: *mut NodeInner<K, V, Logger>) {
+        (*node).next_free.store(NodeInner::DANGLING_PTR, Ordering::Relaxed);
+        if self.tail.is_null() {
+            self.head = node;
         } else {
-            // We increment the refcount of this node to account for its presence
-            // in the tree's free list.
-            this.refcount.fetch_add(1, Ordering::Relaxed);
+            (*self.tail).next_free.store(node, Ordering::Relaxed);
+        }
+        self.tail = node;
+    }
 
-            // The free count is only ever written to when the free list is
-            // locked so we don't need an atomic increment here.
-            let old_free_count = root.free_count.load(Ordering::Relaxed);
-            root.free_count.store(old_free_count + 1, Ordering::Relaxed);
+    /// Puts this chain back at the head of the free list of the given root.
+    ///
+    /// If the tree was dropped in the meantime, the nodes of the chain are
+    /// collected right away instead, and the report is updated accordingly.
+    ///
+    /// # Safety
+    ///
+    /// The given node should be the root of the tree of the nodes of this
+    /// chain.
+    unsafe fn requeue(self, root: &UnsafeNode<K, V, Logger>, report: &mut GcReport) {
+        if self.tail.is_null() {
+            return;
+        }
+        if let Some(old_head) = UnsafeNode::lock_free_list(root) {
+            if root.state().free_capacity.is_some() {
+                let mut prev = NodeInner::DANGLING_PTR;
+                let mut node = self.head;
+                while node != NodeInner::DANGLING_PTR {
+                    (*node).prev_free.store(prev, Ordering::Relaxed);
+                    prev = node;
+                    node = (*node).next_free.load(Ordering::Relaxed);
+                }
+                UnsafeNode::set_prev_free(root, old_head, self.tail);
+            }
+            (*self.tail).next_free.store(old_head, Ordering::Relaxed);
+            root.next_free.store(self.head, Ordering::Release);
+        } else {
+            let collected = UnsafeNode::collect_free_chain(root, self.head, 0);
+            report.freed += collected.freed;
+            report.resurrected += collected.resurrected;
+            report.requeued = 0;
+        }
+    }
+}
 
-            // Finally, we store the old free list head into this node's next free
-            // slot and we unlock the guard with the new head.
-            this.next_free.store(old_head, Ordering::Relaxed);
-            root.next_free.store(this_ptr, Ordering::Release);
+impl<K, V, Logger> Drop for RetainGuard<'_, K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    fn drop(&mut self) {
+        let mut retained = mem::replace(&mut self.retained, FreeChain::new());
+        let mut node = self.rest;
+        while node != NodeInner::DANGLING_PTR {
+            unsafe {
+                let next = (*node).next_free.load(Ordering::Relaxed);
+                retained.push_back(node);
+                node = next;
+            }
         }
-        true
+        unsafe { retained.requeue(self.root, &mut GcReport::default()) };
     }
 }
 
@@ -364,15 +1878,17 @@ where
 {
     fn drop(&mut self) {
         let this = self.as_unsafe_node();
-        if this.refcount.fetch_sub(1, Ordering::Release) != 1 {
-            // This wasn't the last reference to this node, nothing to do
-            // anymore.
+        if this.pinned.load(Ordering::Relaxed) {
+            // This reference didn't touch the refcount of the node if it was
+            // created after the node got pinned, and releasing it otherwise
+            // doesn't matter given pinned nodes are never freed anyway.
             return;
         }
-
         unsafe {
-            if this.root().is_none() || !UnsafeNode::push_on_free_list(this) {
-                UnsafeNode::drop_without_free_list(self.as_unsafe_node_mut())
+            if this.root().is_none() {
+                UnsafeNode::release(this);
+            } else if let Some(root) = UnsafeNode::release_to_free_list(this) {
+                UnsafeNode::trim_free_list(root.as_unsafe_node());
             }
         }
     }
@@ -390,7 +1906,47 @@ where
 impl<K, V, Logger> NodeInner<K, V, Logger> {
     const DANGLING_PTR: *mut NodeInner<K, V, Logger> = NonNull::dangling().as_ptr();
 
+    /// Creates the contents of a new non-root node, without its ancestors.
+    fn new_child(value: V) -> Self {
+        Self {
+            value,
+            marker,
+            ancestors: None,
+            children: Default::default(),
+            refcount: AtomicUsize::new(1),
+            next_free: Default::default(),
+            prev_free: Default::default(),
+            gc_age: Default::default(),
+            state: None,
+            weak_cell: AtomicPtr::new(ptr::null_mut()),
+            pinned: AtomicBool::new(false),
+            looked_up: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the current statistics of the tree of this root node.
+    fn gc_stats(&self) -> GcStats {
+        GcStats {
+            free: self.state().free_count.load(Ordering::Relaxed),
+            allocated: self.state().node_count.load(Ordering::Relaxed),
+            node_size: mem::size_of::<Self>(),
+        }
+    }
+
+    /// Returns the state of the tree of this root node.
+    fn state(&self) -> &TreeState<K, V, Logger> {
+        self.state.as_deref().expect("only root nodes have a tree state")
+    }
+
     pub(crate) fn root(&self) -> Option<&UnsafeNode<K, V, Logger>> {
         self.ancestors.as_ref().map(Ancestors::root)
     }
+
+    pub(crate) fn parent(&self) -> Option<&UnsafeNode<K, V, Logger>> {
+        self.ancestors.as_ref().map(Ancestors::parent)
+    }
+
+    pub(crate) fn value(&self) -> &V {
+        &self.value
+    }
 }
@@ -0,0 +1,35 @@
+use std::error::Error;
+use std::fmt;
+
+/// The error returned by `Node::try_ensure_child_with`.
+#[derive(Debug)]
+pub enum TryEnsureChildError<E> {
+    /// Creating the value of the child failed.
+    Value(E),
+    /// Allocating memory for the child failed.
+    Alloc,
+}
+
+impl<E> fmt::Display for TryEnsureChildError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryEnsureChildError::Value(error) => error.fmt(f),
+            TryEnsureChildError::Alloc => f.write_str("memory allocation failed"),
+        }
+    }
+}
+
+impl<E> Error for TryEnsureChildError<E>
+where
+    E: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TryEnsureChildError::Value(error) => Some(error),
+            TryEnsureChildError::Alloc => None,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::core::NodeInner;
+use crate::logger::Log;
+use crate::node::Node;
+use std::collections::VecDeque;
+use std::hash::Hash;
+
+/// An iterator over a node and its ancestors, from the node up to the root.
+///
+/// This is returned by `Node::ancestors`.
+pub struct AncestorNodes<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    next: Option<Node<K, V, Logger>>,
+}
+
+impl<K, V, Logger> AncestorNodes<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    pub(crate) fn new(node: Node<K, V, Logger>) -> Self {
+        Self { next: Some(node) }
+    }
+}
+
+impl<K, V, Logger> Iterator for AncestorNodes<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    type Item = Node<K, V, Logger>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.parent();
+        Some(node)
+    }
+}
+
+/// An iterator over the values of a node and its ancestors, from the node up
+/// to the root.
+///
+/// This is returned by `Node::ancestor_values` and doesn't touch the refcount
+/// of any node, given the node it was created from keeps all of its ancestors
+/// alive.
+pub struct AncestorValues<'a, K, V, Logger> {
+    next: Option<&'a NodeInner<K, V, Logger>>,
+}
+
+impl<'a, K, V, Logger> AncestorValues<'a, K, V, Logger> {
+    pub(crate) fn new(node: &'a NodeInner<K, V, Logger>) -> Self {
+        Self { next: Some(node) }
+    }
+}
+
+impl<'a, K, V, Logger> Clone for AncestorValues<'a, K, V, Logger> {
+    fn clone(&self) -> Self {
+        Self { next: self.next }
+    }
+}
+
+impl<'a, K, V, Logger> Iterator for AncestorValues<'a, K, V, Logger> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next?;
+        self.next = node.parent().map(|parent| &**parent);
+        Some(node.value())
+    }
+}
+
+/// The order in which `Node::descendants` visits the nodes of a subtree.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Traversal {
+    /// Visits each node before its children, and the whole subtree of a child
+    /// before its next sibling.
+    DepthFirst,
+    /// Visits all the nodes at a given depth before the nodes one level down.
+    BreadthFirst,
+}
+
+/// An iterator over the descendants of a node.
+///
+/// This is returned by `Node::descendants`. The children of each node are
+/// snapshotted when that node is yielded, so the children lock of a node is
+/// only ever held while the snapshot is taken. Siblings are yielded in no
+/// particular order.
+pub struct Descendants<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    traversal: Traversal,
+    pending: VecDeque<Node<K, V, Logger>>,
+}
+
+impl<K, V, Logger> Descendants<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    pub(crate) fn new(node: &Node<K, V, Logger>, traversal: Traversal) -> Self {
+        Self { traversal, pending: node.children().into() }
+    }
+}
+
+impl<K, V, Logger> Iterator for Descendants<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    type Item = Node<K, V, Logger>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = match self.traversal {
+            Traversal::DepthFirst => self.pending.pop_back()?,
+            Traversal::BreadthFirst => self.pending.pop_front()?,
+        };
+        self.pending.extend(node.children());
+        Some(node)
+    }
+}
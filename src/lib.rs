@@ -1,13 +1,35 @@
 extern crate alloc;
 
 mod ancestors;
+mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "collector")]
+mod collector;
 mod core;
+mod error;
+mod iter;
 mod logger;
 mod map;
 mod node;
+mod node_ref;
+mod policy;
+mod report;
 mod tree;
 mod unsafe_box;
+mod visitor;
+mod weak;
 
+pub use self::builder::TreeBuilder;
+#[cfg(feature = "collector")]
+pub use self::collector::Collector;
+pub use self::error::TryEnsureChildError;
+pub use self::iter::{AncestorNodes, AncestorValues, Descendants, Traversal};
 pub use self::logger::{Log, NoopLogger};
 pub use self::node::Node;
+pub use self::node_ref::NodeRef;
+pub use self::policy::{ByteBudget, FixedCount, FreeRatio, GcPolicy, GcStats, Never};
+pub use self::report::GcReport;
 pub use self::tree::Tree;
+pub use self::visitor::Visitor;
+pub use self::weak::WeakNode;
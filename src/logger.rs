@@ -1,6 +1,9 @@
 use std::ffi::c_void;
 
 /// A trait to log creation and destruction of nodes in a tree.
+///
+/// The pointers passed to the logger are the same as the ones returned by
+/// `Node::as_ptr`.
 pub trait Log {
     /// Logs the creation of a new node.
     fn log_new(ptr: *const c_void);
@@ -1,6 +1,10 @@
+use crate::error::TryEnsureChildError;
+use crate::unsafe_box::try_box;
 use fxhash::FxHashMap;
+use std::collections::hash_map::{self, Entry};
 use std::hash::Hash;
 use std::mem;
+use std::option;
 
 pub(crate) struct Map<K, V> {
     inner: MapInner<K, V>,
@@ -32,15 +36,35 @@ where
         }
     }
 
+    pub(crate) fn len(&self) -> usize {
+        match &self.inner {
+            MapInner::Empty => 0,
+            MapInner::One(_) => 1,
+            MapInner::Map(map) => map.len(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn values(&self) -> Values<'_, K, V> {
+        match &self.inner {
+            MapInner::Empty => Values::One(None.into_iter()),
+            MapInner::One(one) => Values::One(Some(one).into_iter()),
+            MapInner::Map(map) => Values::Map(map.values()),
+        }
+    }
+
     pub(crate) fn get_or_insert_with(
         &mut self,
         key: K,
         key_from_value: impl FnOnce(&V) -> K,
-        new_value: impl FnOnce() -> V,
+        new_value: impl FnOnce(&K) -> V,
     ) -> &mut V {
         match self.inner {
             MapInner::Empty => {
-                self.inner = MapInner::One(new_value());
+                self.inner = MapInner::One(new_value(&key));
                 match &mut self.inner {
                     MapInner::One(one) => one,
                     _ => unreachable!(),
@@ -66,13 +90,78 @@ where
                     MapInner::Map(map) => map,
                     _ => unreachable!(),
                 };
-                map.insert(one_key, one).unwrap();
+                map.insert(one_key, one);
                 // But it doesn't matter if f panics, by this point
                 // the map is as before but represented as a map instead
                 // of a single value.
-                map.entry(key).or_insert_with(new_value)
+                Self::entry_or_insert_with(map, key, new_value)
+            }
+            MapInner::Map(ref mut map) => Self::entry_or_insert_with(map, key, new_value),
+        }
+    }
+
+    /// Like `Map::get_or_insert_with`, but reports allocation failures and
+    /// errors from `new_value`, leaving the map untouched in both cases.
+    pub(crate) fn try_get_or_insert_with<E>(
+        &mut self,
+        key: K,
+        key_from_value: impl FnOnce(&V) -> K,
+        new_value: impl FnOnce(&K) -> Result<V, TryEnsureChildError<E>>,
+    ) -> Result<&mut V, TryEnsureChildError<E>> {
+        match self.inner {
+            MapInner::Empty => {
+                self.inner = MapInner::One(new_value(&key)?);
+                match &mut self.inner {
+                    MapInner::One(one) => Ok(one),
+                    _ => unreachable!(),
+                }
+            }
+            MapInner::One(ref one) => {
+                let one_key = key_from_value(one);
+                if key == one_key {
+                    match &mut self.inner {
+                        MapInner::One(one) => return Ok(one),
+                        _ => unreachable!(),
+                    }
+                }
+                // Everything that can fail is done before the single value
+                // is moved out of `self.inner`.
+                let mut map = try_box(FxHashMap::default()).map_err(|_| TryEnsureChildError::Alloc)?;
+                map.try_reserve(2).map_err(|_| TryEnsureChildError::Alloc)?;
+                let value = new_value(&key)?;
+                let one = match mem::replace(&mut self.inner, MapInner::Empty) {
+                    MapInner::One(one) => one,
+                    _ => unreachable!(),
+                };
+                map.insert(one_key, one);
+                self.inner = MapInner::Map(map);
+                match &mut self.inner {
+                    MapInner::Map(map) => Ok(map.entry(key).or_insert(value)),
+                    _ => unreachable!(),
+                }
+            }
+            MapInner::Map(ref mut map) => {
+                if !map.contains_key(&key) {
+                    map.try_reserve(1).map_err(|_| TryEnsureChildError::Alloc)?;
+                    let value = new_value(&key)?;
+                    return Ok(map.entry(key).or_insert(value));
+                }
+                Ok(map.get_mut(&key).unwrap())
+            }
+        }
+    }
+
+    fn entry_or_insert_with(
+        map: &mut FxHashMap<K, V>,
+        key: K,
+        new_value: impl FnOnce(&K) -> V,
+    ) -> &mut V {
+        match map.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = new_value(entry.key());
+                entry.insert(value)
             }
-            MapInner::Map(ref mut map) => map.entry(key).or_insert_with(new_value),
         }
     }
 
@@ -89,3 +178,26 @@ where
         }
     }
 }
+
+pub(crate) enum Values<'a, K, V> {
+    One(option::IntoIter<&'a V>),
+    Map(hash_map::Values<'a, K, V>),
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Values::One(one) => one.next(),
+            Values::Map(map) => map.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Values::One(one) => one.size_hint(),
+            Values::Map(map) => map.size_hint(),
+        }
+    }
+}
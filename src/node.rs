@@ -1,8 +1,9 @@
 use crate::logger::{Log, NoopLogger};
 use crate::unsafe_box::UnsafeBox;
 use crate::core::NodeInner;
+use std::ffi::c_void;
 use std::hash::Hash;
-use std::mem;
+use std::mem::{self, ManuallyDrop};
 
 /// A node in the tree.
 pub struct Node<K, V, Logger = NoopLogger>
@@ -22,16 +23,58 @@ where
     for<'a> &'a V: Into<K>,
     Logger: Log,
 {
+    /// Consumes this node and returns a raw pointer to it, without releasing
+    /// the reference it holds.
+    ///
+    /// The pointer is the same as the one returned by `Node::as_ptr` and
+    /// passed to `Log::log_new` and `Log::log_drop`. The reference must
+    /// eventually be given back to `Node::from_raw` to avoid leaking the
+    /// node.
+    pub fn into_raw(self) -> *const c_void {
+        let ptr = self.as_ptr();
+        mem::forget(self);
+        ptr
+    }
+
+    /// Creates a node from a raw pointer previously returned by
+    /// `Node::into_raw`, taking back the reference it held.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must come from `Node::into_raw` on a node of the same
+    /// type, and each call to `Node::into_raw` must be matched by at most
+    /// one call to this function.
+    pub unsafe fn from_raw(ptr: *const c_void) -> Self {
+        Self::from_unsafe_node(UnsafeNode::from_raw(ptr as *mut NodeInner<K, V, Logger>))
+    }
+
+    /// Returns a raw pointer to this node, which stays valid as long as this
+    /// node is alive.
+    ///
+    /// Two nodes are the same node if and only if they return the same
+    /// pointer.
+    pub fn as_ptr(&self) -> *const c_void {
+        UnsafeNode::as_ptr(&self.inner) as *const c_void
+    }
+
+    /// Calls `f` with the node behind a raw pointer, without taking the
+    /// reference it holds.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must come from `Node::into_raw` on a node of the same
+    /// type, and that reference must not be given back to `Node::from_raw`
+    /// before this function returns.
+    pub unsafe fn with_raw<R>(ptr: *const c_void, f: impl FnOnce(&Self) -> R) -> R {
+        let node = ManuallyDrop::new(Self::from_raw(ptr));
+        f(&node)
+    }
+
     /// Returns a reference to the inner unsafe node.
     pub(crate) fn as_unsafe_node(&self) -> &UnsafeNode<K, V, Logger> {
         &self.inner
     }
 
-    /// Returns a mutable reference to the inner unsafe node.
-    pub(crate) fn as_unsafe_node_mut(&mut self) -> &mut UnsafeNode<K, V, Logger> {
-        &mut self.inner
-    }
-
     /// Consumes this node and converts it to its inner unsafe node.
     pub(crate) fn into_unsafe_node(self) -> UnsafeNode<K, V, Logger> {
         let inner = unsafe { UnsafeNode::clone(&self.inner) };
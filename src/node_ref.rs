@@ -0,0 +1,58 @@
+use crate::logger::{Log, NoopLogger};
+use crate::node::UnsafeNode;
+use std::hash::Hash;
+
+/// A borrowed reference to a node in the tree.
+///
+/// Unlike `Node`, this doesn't touch the refcount of the node when created,
+/// copied or dropped. It borrows from a `Node` or a `Tree`, which keeps the
+/// node and all of its ancestors alive, or from the locked children of its
+/// parent, see `NodeRef::with_child`.
+pub struct NodeRef<'a, K, V, Logger = NoopLogger>
+where
+    K: Eq + Hash,
+    for<'b> &'b V: Into<K>,
+    Logger: Log,
+{
+    inner: &'a UnsafeNode<K, V, Logger>,
+}
+
+impl<'a, K, V, Logger> NodeRef<'a, K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'b> &'b V: Into<K>,
+    Logger: Log,
+{
+    /// Creates a new borrowed reference from an unsafe node.
+    ///
+    /// # Safety
+    ///
+    /// The unsafe node should be kept alive for `'a`.
+    pub(crate) unsafe fn new(inner: &'a UnsafeNode<K, V, Logger>) -> Self {
+        Self { inner }
+    }
+
+    /// Returns a reference to the inner unsafe node.
+    pub(crate) fn as_unsafe_node(&self) -> &'a UnsafeNode<K, V, Logger> {
+        self.inner
+    }
+}
+
+impl<K, V, Logger> Clone for NodeRef<'_, K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'b> &'b V: Into<K>,
+    Logger: Log,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V, Logger> Copy for NodeRef<'_, K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'b> &'b V: Into<K>,
+    Logger: Log,
+{
+}
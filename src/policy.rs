@@ -0,0 +1,87 @@
+/// Statistics about a tree, used by a `GcPolicy` to decide whether
+/// `Tree::maybe_gc` should run the garbage collector.
+///
+/// These are only approximations if other threads are concurrently creating
+/// or dropping nodes, or running the garbage collector.
+#[derive(Clone, Copy, Debug)]
+pub struct GcStats {
+    /// The number of nodes waiting on the free list of the tree.
+    pub free: usize,
+    /// The number of nodes currently allocated in the tree, the root
+    /// excluded, and including the nodes waiting on the free list.
+    pub allocated: usize,
+    /// The size in bytes of a single node, not including any memory owned
+    /// by its value.
+    pub node_size: usize,
+}
+
+impl GcStats {
+    /// Returns the number of allocated nodes which are not waiting on the
+    /// free list.
+    pub fn live(&self) -> usize {
+        self.allocated.saturating_sub(self.free)
+    }
+}
+
+/// A policy deciding when `Tree::maybe_gc` should run the garbage collector.
+pub trait GcPolicy: Send + Sync {
+    /// Returns whether the garbage collector should run, given the current
+    /// statistics of the tree.
+    fn should_collect(&self, stats: &GcStats) -> bool;
+}
+
+/// Collects when more than a given number of nodes are waiting on the free
+/// list.
+///
+/// This is the default policy, with a threshold of 300 nodes.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedCount(pub usize);
+
+impl Default for FixedCount {
+    /// Nobody knows why the default threshold is this value, not even Gecko
+    /// people.
+    fn default() -> Self {
+        FixedCount(300)
+    }
+}
+
+impl GcPolicy for FixedCount {
+    fn should_collect(&self, stats: &GcStats) -> bool {
+        stats.free > self.0
+    }
+}
+
+/// Collects when the number of nodes waiting on the free list exceeds the
+/// given ratio of live nodes.
+#[derive(Clone, Copy, Debug)]
+pub struct FreeRatio(pub f64);
+
+impl GcPolicy for FreeRatio {
+    fn should_collect(&self, stats: &GcStats) -> bool {
+        stats.free as f64 > stats.live() as f64 * self.0
+    }
+}
+
+/// Collects when the estimated memory used by the nodes waiting on the free
+/// list exceeds the given number of bytes.
+///
+/// Only the memory of the nodes themselves is taken into account, not any
+/// memory owned by their values.
+#[derive(Clone, Copy, Debug)]
+pub struct ByteBudget(pub usize);
+
+impl GcPolicy for ByteBudget {
+    fn should_collect(&self, stats: &GcStats) -> bool {
+        stats.free.saturating_mul(stats.node_size) > self.0
+    }
+}
+
+/// Never collects, `Tree::gc` must be called explicitly instead.
+#[derive(Clone, Copy, Debug)]
+pub struct Never;
+
+impl GcPolicy for Never {
+    fn should_collect(&self, _stats: &GcStats) -> bool {
+        false
+    }
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+/// A report of what a run of the garbage collector did.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GcReport {
+    /// The number of free list entries the garbage collector processed.
+    pub processed: usize,
+    /// The number of nodes actually freed. This includes the parents that
+    /// were freed in cascade because their last child was.
+    pub freed: usize,
+    /// The number of free list entries that were resurrected in the meantime
+    /// and thus were skipped.
+    pub resurrected: usize,
+    /// The number of free list entries that were too young to be freed and
+    /// were requeued, see `TreeBuilder::gc_generations`.
+    pub requeued: usize,
+    /// How long the garbage collector took.
+    pub elapsed: Duration,
+}
@@ -1,12 +1,25 @@
+#[cfg(feature = "collector")]
+use crate::collector::Shared;
 use crate::logger::{Log, NoopLogger};
 use crate::node::{Node, UnsafeNode};
+use crate::node_ref::NodeRef;
+use crate::policy::GcPolicy;
+#[cfg(feature = "collector")]
+use parking_lot::Mutex;
+use std::borrow::Borrow;
 use std::hash::Hash;
+use std::sync::Arc;
+#[cfg(feature = "collector")]
+use std::sync::Weak;
 
 /// A tree of values.
 ///
 /// Children of nodes aren't immediately dropped when their refcount reaches 0,
 /// instead they are put on a free list owned by the tree itself, which will
 /// only be emptied if the tree is dropped or `Tree::gc` is called.
+///
+/// Dropping a tree with pinned nodes leaks them, along with the root of the
+/// tree, see `Node::pin` and `Tree::has_pinned`.
 pub struct Tree<K, V, Logger = NoopLogger>
 where
     K: Eq + Hash,
@@ -14,6 +27,11 @@ where
     Logger: Log,
 {
     root: Node<K, V, Logger>,
+    gc_policy: Arc<dyn GcPolicy>,
+    gc_generations: usize,
+    /// The collectors of this tree, which are stopped when it is dropped.
+    #[cfg(feature = "collector")]
+    collectors: Mutex<Vec<Weak<Shared>>>,
 }
 
 impl<K, V, Logger> Tree<K, V, Logger>
@@ -27,14 +45,87 @@ where
         &self.root
     }
 
+    /// Returns a borrowed reference to the root of the tree.
+    pub fn root_ref(&self) -> NodeRef<'_, K, V, Logger> {
+        self.root.as_node_ref()
+    }
+
+    /// Returns a reference to the value the tree was created with.
+    pub fn root_value(&self) -> &V {
+        self.root.value()
+    }
+
+    /// Returns the node at the end of the given path of keys from the root,
+    /// if all of them exist.
+    ///
+    /// See `Node::find_path`.
+    pub fn find_path<I>(&self, keys: I) -> Option<Node<K, V, Logger>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        self.root.find_path(keys)
+    }
+
+    /// Returns the deepest node that exists along the given path of keys from
+    /// the root, with the number of keys that were matched to reach it.
+    ///
+    /// See `Node::longest_prefix`.
+    pub fn longest_prefix<I>(&self, keys: I) -> (Node<K, V, Logger>, usize)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        self.root.longest_prefix(keys)
+    }
+
     /// Creates a new tree from a root node.
     ///
     /// # Safety
     ///
     /// The node should be a root.
-    pub(crate) unsafe fn from_root_node(root: Node<K, V, Logger>) -> Self {
+    pub(crate) unsafe fn from_root_node(
+        root: Node<K, V, Logger>,
+        gc_policy: Arc<dyn GcPolicy>,
+        gc_generations: usize,
+    ) -> Self {
         debug_assert!(root.as_unsafe_node().root().is_none());
-        Self { root }
+        Self {
+            root,
+            gc_policy,
+            gc_generations,
+            #[cfg(feature = "collector")]
+            collectors: Default::default(),
+        }
+    }
+
+    /// Returns a reference to the policy consulted by `Tree::maybe_gc`.
+    pub(crate) fn gc_policy(&self) -> &Arc<dyn GcPolicy> {
+        &self.gc_policy
+    }
+
+    /// Returns how many garbage collections an unused node must survive
+    /// before being freed.
+    pub(crate) fn gc_generations(&self) -> usize {
+        self.gc_generations
+    }
+
+    /// Registers a collector to stop when this tree is dropped.
+    #[cfg(feature = "collector")]
+    pub(crate) fn register_collector(&self, collector: &Arc<Shared>) {
+        let mut collectors = self.collectors.lock();
+        collectors.retain(|collector| collector.strong_count() != 0);
+        collectors.push(Arc::downgrade(collector));
+    }
+
+    /// Stops the collectors of this tree.
+    #[cfg(feature = "collector")]
+    pub(crate) fn stop_collectors(&self) {
+        for collector in self.collectors.lock().drain(..) {
+            if let Some(collector) = collector.upgrade() {
+                collector.stop();
+            }
+        }
     }
 
     /// Returns a reference to the inner unsafe node.
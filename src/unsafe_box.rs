@@ -1,3 +1,4 @@
+use std::alloc::Layout;
 use std::mem::ManuallyDrop;
 use std::ops::Deref;
 use std::ptr;
@@ -13,6 +14,12 @@ impl<T> UnsafeBox<T> {
         Self { inner: ManuallyDrop::new(Box::new(value)) }
     }
 
+    /// Tries to create a new unsafe box, returning the value back if memory
+    /// allocation failed.
+    pub(crate) fn try_new(value: T) -> Result<Self, T> {
+        Ok(Self { inner: ManuallyDrop::new(try_box(value)?) })
+    }
+
     /// Creates a new box from a pointer.
     ///
     /// # Safety
@@ -22,6 +29,11 @@ impl<T> UnsafeBox<T> {
         Self { inner: ManuallyDrop::new(Box::from_raw(ptr)) }
     }
 
+    /// Returns a raw pointer to the inner value of this unsafe box.
+    pub(crate) fn as_ptr(this: &Self) -> *const T {
+        &**this.inner
+    }
+
     /// Creates a new unsafe box from an existing one.
     ///
     /// # Safety
@@ -60,3 +72,21 @@ impl<T> Deref for UnsafeBox<T> {
         &self.inner
     }
 }
+
+/// Tries to allocate a new box, returning the value back if memory allocation
+/// failed instead of aborting.
+pub(crate) fn try_box<T>(value: T) -> Result<Box<T>, T> {
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        // Zero-sized values are never actually allocated.
+        return Ok(Box::new(value));
+    }
+    unsafe {
+        let ptr = alloc::alloc::alloc(layout) as *mut T;
+        if ptr.is_null() {
+            return Err(value);
+        }
+        ptr.write(value);
+        Ok(Box::from_raw(ptr))
+    }
+}
@@ -0,0 +1,17 @@
+use crate::logger::Log;
+use crate::node::Node;
+use std::hash::Hash;
+
+/// A visitor of the nodes of a subtree, driven by `Node::walk`.
+pub trait Visitor<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Called when entering a node, before any of its children is visited.
+    fn enter(&mut self, _node: &Node<K, V, Logger>) {}
+
+    /// Called when leaving a node, after all of its children were visited.
+    fn leave(&mut self, _node: &Node<K, V, Logger>) {}
+}
@@ -0,0 +1,51 @@
+use crate::logger::{Log, NoopLogger};
+use crate::node::UnsafeNode;
+use parking_lot::Mutex;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A weak reference to a node in the tree.
+///
+/// This doesn't keep the node alive, and doesn't prevent it from being put
+/// on the free list of the tree nor from being freed by the garbage
+/// collector.
+pub struct WeakNode<K, V, Logger = NoopLogger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    cell: Arc<WeakCell<K, V, Logger>>,
+}
+
+/// The cell shared by a node and its weak references, which is emptied when
+/// the node is freed.
+pub(crate) type WeakCell<K, V, Logger> = Mutex<Option<UnsafeNode<K, V, Logger>>>;
+
+impl<K, V, Logger> WeakNode<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    /// Creates a new weak reference from a cell.
+    pub(crate) fn new(cell: Arc<WeakCell<K, V, Logger>>) -> Self {
+        Self { cell }
+    }
+
+    /// Returns a reference to the cell of this weak reference.
+    pub(crate) fn as_cell(&self) -> &WeakCell<K, V, Logger> {
+        &self.cell
+    }
+}
+
+impl<K, V, Logger> Clone for WeakNode<K, V, Logger>
+where
+    K: Eq + Hash,
+    for<'a> &'a V: Into<K>,
+    Logger: Log,
+{
+    fn clone(&self) -> Self {
+        Self::new(self.cell.clone())
+    }
+}
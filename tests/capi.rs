@@ -0,0 +1,39 @@
+//! Runs the C test program compiled by the build script, see `capi/test.c`,
+//! and checks that the C header in `include/` is up to date.
+
+use std::env;
+use std::fs;
+use std::os::raw::c_int;
+use std::process;
+
+// Makes sure the C API gets linked in.
+use recycling_tree as _;
+
+extern "C" {
+    fn recycling_tree_capi_test() -> c_int;
+}
+
+/// The C header generated by the build script.
+const GENERATED_HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/recycling_tree.h"));
+
+/// The path of the C header C programs should use, relative to the crate.
+const HEADER_PATH: &str = "include/recycling_tree.h";
+
+fn main() {
+    let path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), HEADER_PATH);
+    if env::var_os("UPDATE_C_HEADER").is_some() {
+        fs::write(&path, GENERATED_HEADER).expect("failed to write the C header");
+    } else if fs::read_to_string(&path).ok().as_deref() != Some(GENERATED_HEADER) {
+        eprintln!(
+            "{} is out of date, regenerate it with \
+             `UPDATE_C_HEADER=1 cargo test --features capi --test capi`",
+            HEADER_PATH,
+        );
+        process::exit(1);
+    }
+    let status = unsafe { recycling_tree_capi_test() };
+    if status != 0 {
+        process::exit(status);
+    }
+    println!("capi test passed");
+}
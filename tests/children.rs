@@ -0,0 +1,72 @@
+mod common;
+
+use common::{live_nodes, tree, LocalLogger, Value};
+use recycling_tree::Node;
+
+/// Returns the keys of the children of a node, in ascending order.
+fn child_keys(node: &Node<u32, Value, LocalLogger>) -> Vec<u32> {
+    let mut keys = node.children().iter().map(|child| child.value().0).collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys
+}
+
+#[test]
+fn node_with_two_children() {
+    let tree = tree();
+    let one = tree.root().ensure_child(Value(1));
+    let two = tree.root().ensure_child(Value(2));
+    assert_eq!(tree.root().get_child(&1).unwrap().value().0, 1);
+    assert_eq!(tree.root().get_child(&2).unwrap().value().0, 2);
+    assert_eq!(tree.root().ensure_child(Value(1)).value().0, one.value().0);
+    assert_eq!(tree.root().ensure_child(Value(2)).value().0, two.value().0);
+}
+
+#[test]
+fn children_follow_insertions_and_removals() {
+    let tree = tree();
+    let root = tree.root();
+    assert!(!root.has_children());
+    assert_eq!(root.child_count(), 0);
+    assert!(root.children().is_empty());
+
+    let one = root.ensure_child(Value(1));
+    assert!(root.has_children());
+    assert_eq!(root.child_count(), 1);
+    assert_eq!(child_keys(root), [1]);
+
+    let others = (2..=4).map(|i| root.ensure_child(Value(i))).collect::<Vec<_>>();
+    assert_eq!(root.child_count(), 4);
+    assert_eq!(child_keys(root), [1, 2, 3, 4]);
+    // Children waiting on the free list are included.
+    drop(others);
+    assert_eq!(tree.free_list_len(), 3);
+    assert_eq!(child_keys(root), [1, 2, 3, 4]);
+
+    // The last child left is still found after the others are freed.
+    assert_eq!(tree.gc().freed, 3);
+    assert_eq!(root.child_count(), 1);
+    assert_eq!(child_keys(root), [1]);
+    drop(root.ensure_child(Value(5)));
+    assert_eq!(child_keys(root), [1, 5]);
+
+    drop(one);
+    assert_eq!(tree.gc().freed, 2);
+    assert!(!root.has_children());
+    assert!(root.children().is_empty());
+    assert_eq!(child_keys(&root.ensure_child(Value(6))), []);
+    assert_eq!(child_keys(root), [6]);
+    assert_eq!(live_nodes(), 2);
+}
+
+#[test]
+fn single_children_can_be_replaced() {
+    let tree = tree();
+    drop(tree.root().ensure_child(Value(1)));
+    tree.gc();
+    assert!(!tree.root().has_children());
+    drop(tree.root().ensure_child(Value(2)));
+    assert_eq!(child_keys(tree.root()), [2]);
+    assert!(tree.root().get_child(&1).is_none());
+    drop(tree.root().ensure_child(Value(3)));
+    assert_eq!(child_keys(tree.root()), [2, 3]);
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::Value;
+use recycling_tree::{Collector, FixedCount, GcPolicy, GcStats, Tree};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Nodes are freed by the collector threads, so the trees of these tests
+// don't use the thread-local logger of the other tests.
+type CollectedTree = Tree<u32, Value>;
+
+/// Waits for at most a few seconds for `f` to return true.
+fn wait_until(mut f: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if f() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    false
+}
+
+#[test]
+fn collector_follows_the_gc_policy() {
+    let tree = CollectedTree::builder().gc_policy(FixedCount(4)).build(Value(0));
+    let collector = Collector::spawn(&tree, Duration::from_millis(1)).unwrap();
+    for i in 0..4 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    thread::sleep(Duration::from_millis(50));
+    assert!(collector.last_report().is_none());
+    assert_eq!(tree.free_list_len(), 4);
+
+    drop(tree.root().ensure_child(Value(4)));
+    assert!(wait_until(|| collector.last_report().is_some()));
+    let report = collector.last_report().unwrap();
+    assert_eq!(report.freed, 5);
+    assert_eq!(tree.free_list_len(), 0);
+    assert_eq!(tree.gc_stats().allocated, 0);
+    assert!(!collector.is_finished());
+}
+
+#[test]
+fn collector_stops_when_the_tree_is_dropped() {
+    let tree = CollectedTree::new(Value(0));
+    let collector = Collector::spawn(&tree, Duration::from_secs(3600)).unwrap();
+    let node = tree.root().ensure_child(Value(1));
+    assert!(!collector.is_finished());
+
+    // The thread is woken up right away, even though the root is kept alive
+    // by the node.
+    let start = Instant::now();
+    drop(tree);
+    assert!(wait_until(|| collector.is_finished()));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(collector.last_report().is_none());
+    drop(node);
+}
+
+#[test]
+fn dropping_the_collector_joins_its_thread() {
+    #[derive(Default)]
+    struct Calls {
+        started: AtomicUsize,
+        finished: AtomicUsize,
+    }
+
+    struct SlowPolicy(Arc<Calls>);
+
+    impl GcPolicy for SlowPolicy {
+        fn should_collect(&self, _: &GcStats) -> bool {
+            self.0.started.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            self.0.finished.fetch_add(1, Ordering::SeqCst);
+            false
+        }
+    }
+
+    let calls = Arc::new(Calls::default());
+    let tree = CollectedTree::builder().gc_policy(SlowPolicy(calls.clone())).build(Value(0));
+    let collector = Collector::spawn(&tree, Duration::from_millis(1)).unwrap();
+    assert!(wait_until(|| calls.started.load(Ordering::SeqCst) != 0));
+
+    // The thread must not be in the middle of a call to the policy anymore,
+    // nor start a new one.
+    drop(collector);
+    let finished = calls.finished.load(Ordering::SeqCst);
+    assert_eq!(calls.started.load(Ordering::SeqCst), finished);
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(calls.started.load(Ordering::SeqCst), finished);
+}
@@ -0,0 +1,164 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use recycling_tree::{Log, Tree, TreeBuilder};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// The value of the nodes of test trees, keyed by its number.
+#[derive(Debug)]
+pub struct Value(pub u32);
+
+impl From<&Value> for u32 {
+    fn from(value: &Value) -> Self {
+        value.0
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        let _ = DROPPED_VALUES.try_with(|dropped| dropped.borrow_mut().push(self.0));
+    }
+}
+
+pub type TestTree = Tree<u32, Value, LocalLogger>;
+
+thread_local! {
+    static LIVE_NODES: RefCell<HashSet<usize>> = RefCell::new(HashSet::new());
+    static DROPPED_VALUES: RefCell<Vec<u32>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A logger tracking the nodes allocated and freed by the current thread,
+/// which panics if a node is freed twice.
+///
+/// Tests using it must create and drop all their nodes on the test thread.
+pub struct LocalLogger;
+
+impl Log for LocalLogger {
+    fn log_new(ptr: *const c_void) {
+        let inserted = LIVE_NODES.with(|live| live.borrow_mut().insert(ptr as usize));
+        assert!(inserted, "node {:?} allocated twice", ptr);
+    }
+
+    fn log_drop(ptr: *const c_void) {
+        let removed = LIVE_NODES.with(|live| live.borrow_mut().remove(&(ptr as usize)));
+        assert!(removed, "node {:?} freed twice", ptr);
+    }
+}
+
+/// Returns the number of nodes allocated by the current thread which are
+/// not freed yet, roots included.
+pub fn live_nodes() -> usize {
+    LIVE_NODES.with(|live| live.borrow().len())
+}
+
+/// Returns whether the node with the given address, as passed to the
+/// logger, was allocated by the current thread and not freed yet.
+pub fn is_live_node(ptr: *const c_void) -> bool {
+    LIVE_NODES.with(|live| live.borrow().contains(&(ptr as usize)))
+}
+
+static GLOBAL_NEW: AtomicUsize = AtomicUsize::new(0);
+static GLOBAL_DROP: AtomicUsize = AtomicUsize::new(0);
+static GLOBAL_LIVE_NODES: Mutex<Option<HashSet<usize>>> = Mutex::new(None);
+static GLOBAL_DROP_HOOK: Mutex<Option<fn()>> = Mutex::new(None);
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// A logger tracking the nodes allocated and freed by all threads, which
+/// panics if a node is freed twice.
+///
+/// Tests using it must hold the guard returned by `serialize`, given they
+/// share it.
+pub struct GlobalLogger;
+
+impl Log for GlobalLogger {
+    fn log_new(ptr: *const c_void) {
+        GLOBAL_NEW.fetch_add(1, Ordering::SeqCst);
+        let mut live = GLOBAL_LIVE_NODES.lock().unwrap();
+        let inserted = live.get_or_insert_with(HashSet::new).insert(ptr as usize);
+        assert!(inserted, "node {:?} allocated twice", ptr);
+    }
+
+    fn log_drop(ptr: *const c_void) {
+        GLOBAL_DROP.fetch_add(1, Ordering::SeqCst);
+        {
+            let mut live = GLOBAL_LIVE_NODES.lock().unwrap();
+            let removed = live.get_or_insert_with(HashSet::new).remove(&(ptr as usize));
+            assert!(removed, "node {:?} freed twice", ptr);
+        }
+        let hook = *GLOBAL_DROP_HOOK.lock().unwrap();
+        if let Some(hook) = hook {
+            hook();
+        }
+    }
+}
+
+/// Serializes the tests using `GlobalLogger`, for as long as the returned
+/// guard is alive.
+pub fn serialize() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+/// Sets a function called by `GlobalLogger` whenever a node is freed, after
+/// accounting for it.
+pub fn set_drop_hook(hook: Option<fn()>) {
+    *GLOBAL_DROP_HOOK.lock().unwrap() = hook;
+}
+
+/// Returns the number of nodes freed by all threads so far.
+pub fn global_dropped() -> usize {
+    GLOBAL_DROP.load(Ordering::SeqCst)
+}
+
+/// Returns the number of nodes allocated by all threads which are not freed
+/// yet, roots included.
+pub fn global_live_nodes() -> usize {
+    let live = GLOBAL_LIVE_NODES.lock().unwrap();
+    let count = live.as_ref().map_or(0, HashSet::len);
+    assert_eq!(GLOBAL_NEW.load(Ordering::SeqCst) - GLOBAL_DROP.load(Ordering::SeqCst), count);
+    count
+}
+
+/// Returns the numbers of the values dropped by the current thread since the
+/// last call, in ascending order.
+pub fn dropped_values() -> Vec<u32> {
+    let mut dropped = DROPPED_VALUES.with(|dropped| dropped.take());
+    dropped.sort_unstable();
+    dropped
+}
+
+/// Creates a new test tree with the default configuration.
+pub fn tree() -> TestTree {
+    Tree::with_logger(Value(0))
+}
+
+/// Returns a builder for test trees.
+pub fn builder() -> TreeBuilder<u32, Value, LocalLogger> {
+    Tree::builder()
+}
+
+/// Returns the keys of the values from the root down to the given node,
+/// root excluded.
+pub fn path(node: &recycling_tree::Node<u32, Value, LocalLogger>) -> Vec<u32> {
+    node.path_from_root().iter().skip(1).map(|value| value.0).collect()
+}
+
+/// A small xorshift generator, so that stress tests are reproducible.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        ((self.0 >> 32) as u32) % n
+    }
+}
@@ -0,0 +1,105 @@
+//! Runs collections of the same tree from many threads at once.
+
+mod common;
+
+use common::{GlobalLogger, Value};
+use recycling_tree::{FixedCount, GcReport, Tree};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Whether `block` should block until `UNBLOCK` is set.
+static BLOCK: AtomicBool = AtomicBool::new(false);
+static BLOCKED: AtomicBool = AtomicBool::new(false);
+static UNBLOCK: AtomicBool = AtomicBool::new(false);
+
+type GcTree = Tree<u32, Value, GlobalLogger>;
+
+/// Blocks the thread freeing a node if requested, see `BLOCK`.
+fn block() {
+    if BLOCK.load(Ordering::SeqCst) {
+        BLOCKED.store(true, Ordering::SeqCst);
+        while !UNBLOCK.load(Ordering::SeqCst) {
+            thread::yield_now();
+        }
+    }
+}
+
+#[test]
+fn try_gc_skips_while_collecting() {
+    let _serial = common::serialize();
+    let tree = Arc::new(GcTree::builder().gc_policy(FixedCount(0)).build(Value(0)));
+    drop(tree.root().ensure_child(Value(1)));
+
+    common::set_drop_hook(Some(block));
+    BLOCK.store(true, Ordering::SeqCst);
+    let collector = {
+        let tree = tree.clone();
+        thread::spawn(move || tree.gc())
+    };
+    while !BLOCKED.load(Ordering::SeqCst) {
+        thread::yield_now();
+    }
+    BLOCK.store(false, Ordering::SeqCst);
+
+    drop(tree.root().ensure_child(Value(2)));
+    assert!(tree.try_gc().is_none());
+    assert!(tree.maybe_gc().is_none());
+    assert_eq!(tree.free_list_len(), 1);
+
+    UNBLOCK.store(true, Ordering::SeqCst);
+    let report = collector.join().unwrap();
+    common::set_drop_hook(None);
+    assert_eq!(report.processed, 1);
+    assert_eq!(report.freed, 1);
+
+    let report = tree.try_gc().unwrap();
+    assert_eq!(report.freed, 1);
+    drop(tree.root().ensure_child(Value(3)));
+    assert_eq!(tree.maybe_gc().unwrap().freed, 1);
+    drop(tree);
+    assert_eq!(common::global_live_nodes(), 0);
+}
+
+#[test]
+fn concurrent_collections_free_nodes_once() {
+    const THREADS: u32 = 8;
+    const ITERATIONS: u32 = 2_000;
+
+    let _serial = common::serialize();
+    let dropped = common::global_dropped();
+    let tree = Arc::new(GcTree::builder().gc_policy(FixedCount(8)).build(Value(0)));
+    let threads = (0..THREADS)
+        .map(|i| {
+            let tree = tree.clone();
+            thread::spawn(move || {
+                let mut freed = 0;
+                let mut add = |report: Option<GcReport>| {
+                    if let Some(report) = report {
+                        let kept = report.resurrected + report.requeued;
+                        assert!(report.freed + kept >= report.processed);
+                        freed += report.freed;
+                    }
+                };
+                for j in 0..ITERATIONS {
+                    let keys = [j % 7, (i + j) % 5, j % 3];
+                    drop(tree.root().ensure_path(keys.iter().map(|&key| Value(key))));
+                    match j % 3 {
+                        0 => add(Some(tree.gc())),
+                        1 => add(tree.try_gc()),
+                        _ => add(tree.maybe_gc()),
+                    }
+                }
+                freed
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut freed = threads.into_iter().map(|thread| thread.join().unwrap()).sum::<usize>();
+    freed += tree.gc().freed;
+
+    assert_eq!(tree.free_list_len(), 0);
+    assert_eq!(tree.gc_stats().allocated, 0);
+    assert_eq!(common::global_dropped() - dropped, freed);
+    drop(tree);
+    assert_eq!(common::global_live_nodes(), 0);
+}
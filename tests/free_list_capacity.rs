@@ -0,0 +1,155 @@
+mod common;
+
+use common::{builder, dropped_values, live_nodes, LocalLogger, TestTree, Value};
+use recycling_tree::{Node, Traversal, Visitor};
+
+fn bounded(capacity: usize) -> TestTree {
+    builder().free_list_capacity(capacity).build(Value(0))
+}
+
+#[test]
+fn least_recently_released_nodes_are_evicted() {
+    let tree = bounded(4);
+    for i in 1..=4 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    assert_eq!(tree.free_list_len(), 4);
+    assert_eq!(dropped_values(), []);
+
+    // Releasing a node again makes it the most recently released one.
+    drop(tree.root().get_child(&1).unwrap());
+    drop(tree.root().ensure_child(Value(5)));
+    assert_eq!(tree.free_list_len(), 4);
+    assert_eq!(dropped_values(), [2]);
+
+    drop(tree.root().ensure_child(Value(6)));
+    drop(tree.root().ensure_child(Value(7)));
+    assert_eq!(dropped_values(), [3, 4]);
+    assert_eq!(tree.root().child_count(), 4);
+    assert_eq!(live_nodes(), 5);
+}
+
+#[test]
+fn traversals_do_not_reorder_the_free_list() {
+    struct Counter(usize);
+
+    impl Visitor<u32, Value, LocalLogger> for Counter {
+        fn enter(&mut self, _node: &Node<u32, Value, LocalLogger>) {
+            self.0 += 1;
+        }
+    }
+
+    let tree = bounded(3);
+    for i in 1..=3 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    assert_eq!(tree.root().children().len(), 3);
+    assert_eq!(tree.root().descendants(Traversal::DepthFirst).count(), 3);
+    assert_eq!(tree.root().descendants(Traversal::BreadthFirst).count(), 3);
+    let mut counter = Counter(0);
+    tree.root().walk(&mut counter);
+    assert_eq!(counter.0, 4);
+    assert_eq!(tree.invalidate(&99), 0);
+
+    drop(tree.root().ensure_child(Value(4)));
+    assert_eq!(dropped_values(), [1]);
+    drop(tree.root().ensure_child(Value(5)));
+    assert_eq!(dropped_values(), [2]);
+}
+
+#[test]
+fn free_list_is_trimmed_to_its_capacity() {
+    let tree = bounded(10);
+    for i in 1..=20 {
+        drop(tree.root().ensure_child(Value(i)));
+        assert_eq!(tree.free_list_len(), i.min(10) as usize);
+    }
+    assert_eq!(dropped_values(), (1..=10).collect::<Vec<_>>());
+    assert_eq!(live_nodes(), 11);
+
+    let report = tree.gc();
+    assert_eq!(report.freed, 10);
+    assert_eq!(dropped_values(), (11..=20).collect::<Vec<_>>());
+}
+
+#[test]
+fn evicted_nodes_release_their_parents() {
+    let tree = bounded(1);
+    drop(tree.root().ensure_path((1..=3).map(Value)));
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(live_nodes(), 4);
+
+    // Evicting the leaf pushes its parent on the free list, which evicts
+    // the node released before it.
+    drop(tree.root().ensure_child(Value(4)));
+    assert_eq!(dropped_values(), [3, 4]);
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(live_nodes(), 3);
+}
+
+#[test]
+fn resurrected_nodes_are_not_evicted() {
+    let tree = bounded(1);
+    drop(tree.root().ensure_child(Value(1)));
+    let node = tree.root().ensure_child(Value(2));
+    drop(tree.root().get_child(&1).unwrap());
+    let resurrected = tree.root().get_child(&1).unwrap();
+    drop(node);
+    // The first node was evicted from the free list but not freed.
+    assert_eq!(dropped_values(), []);
+    assert_eq!(tree.free_list_len(), 1);
+
+    drop(resurrected);
+    assert_eq!(dropped_values(), [2]);
+    assert_eq!(tree.free_list_len(), 1);
+}
+
+#[test]
+fn eviction_skips_locked_parents() {
+    let tree = bounded(1);
+    drop(tree.root().ensure_child(Value(1)));
+    let node = tree.root().ensure_child(Value(2));
+    let root = tree.root();
+    // The children of the root are locked while the closure runs, so the
+    // eviction of the first node must be postponed instead of deadlocking.
+    let new = root.ensure_child_with(3, || {
+        drop(node);
+        Value(3)
+    });
+    assert_eq!(tree.free_list_len(), 2);
+    assert_eq!(dropped_values(), []);
+    drop(new);
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(dropped_values(), [1, 2]);
+}
+
+#[test]
+fn collections_keep_the_free_list_bounded() {
+    let tree = builder().free_list_capacity(4).gc_generations(1).build(Value(0));
+    for i in 1..=4 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    // The two most recently released nodes are aged and requeued, then the
+    // two other ones.
+    assert_eq!(tree.gc_step(2).requeued, 2);
+    let report = tree.gc();
+    assert_eq!(report.requeued, 2);
+    assert_eq!(report.freed, 2);
+    assert_eq!(dropped_values(), [3, 4]);
+    assert_eq!(tree.free_list_len(), 2);
+
+    // Requeued nodes keep their order in the free list.
+    for i in 5..=7 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    assert_eq!(dropped_values(), [1]);
+    assert_eq!(tree.free_list_len(), 4);
+
+    assert_eq!(tree.retain(|value| value.0 >= 5).freed, 1);
+    assert_eq!(dropped_values(), [2]);
+    drop(tree.root().get_child(&5).unwrap());
+    drop(tree.root().ensure_child(Value(8)));
+    drop(tree.root().ensure_child(Value(9)));
+    assert_eq!(dropped_values(), [6]);
+    assert_eq!(tree.free_list_len(), 4);
+}
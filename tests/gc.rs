@@ -0,0 +1,149 @@
+mod common;
+
+use common::{builder, live_nodes, tree, Value};
+use recycling_tree::FixedCount;
+
+#[test]
+fn gc_frees_unused_nodes() {
+    let tree = tree();
+    for i in 1..=10 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    assert_eq!(tree.free_list_len(), 10);
+    assert_eq!(live_nodes(), 11);
+
+    let report = tree.gc();
+    assert_eq!(report.processed, 10);
+    assert_eq!(report.freed, 10);
+    assert_eq!(report.resurrected, 0);
+    assert_eq!(tree.free_list_len(), 0);
+    assert_eq!(live_nodes(), 1);
+    assert!(!tree.root().has_children());
+
+    // The collected nodes must not have been pushed back on the free list.
+    let report = tree.gc();
+    assert_eq!(report.processed, 0);
+    assert_eq!(report.freed, 0);
+
+    drop(tree);
+    assert_eq!(live_nodes(), 0);
+}
+
+#[test]
+fn gc_frees_parents_in_cascade() {
+    let tree = tree();
+    let leaf = tree.root().ensure_path((1..=3).map(Value));
+    let sibling = tree.root().ensure_path((4..=5).map(Value));
+    assert_eq!(live_nodes(), 6);
+    drop(leaf);
+    // Only the leaf goes on the free list, its ancestors are kept alive by
+    // the reference it holds to its parent.
+    assert_eq!(tree.free_list_len(), 1);
+
+    let report = tree.gc();
+    assert_eq!(report.processed, 1);
+    assert_eq!(report.freed, 3);
+    assert_eq!(tree.free_list_len(), 0);
+    assert_eq!(live_nodes(), 3);
+    assert!(tree.find_path([1]).is_none());
+    assert!(tree.find_path([4, 5]).is_some());
+
+    drop(sibling);
+    assert_eq!(tree.gc().freed, 2);
+    assert_eq!(live_nodes(), 1);
+}
+
+#[test]
+fn resurrected_nodes_survive_gc() {
+    let tree = tree();
+    drop(tree.root().ensure_child(Value(1)));
+    assert_eq!(tree.free_list_len(), 1);
+
+    let node = tree.root().get_child(&1).unwrap();
+    let report = tree.gc();
+    assert_eq!(report.processed, 1);
+    assert_eq!(report.freed, 0);
+    assert_eq!(report.resurrected, 1);
+    assert_eq!(tree.free_list_len(), 0);
+    assert_eq!(live_nodes(), 2);
+
+    // The node is still in the tree and goes back on the free list once
+    // unused again.
+    assert_eq!(tree.root().get_child(&1).unwrap().value().0, 1);
+    assert_eq!(node.value().0, 1);
+    drop(node);
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(tree.gc().freed, 1);
+    assert_eq!(live_nodes(), 1);
+}
+
+#[test]
+fn nodes_are_pushed_once_on_the_free_list() {
+    let tree = tree();
+    let node = tree.root().ensure_child(Value(1));
+    let clone = node.clone();
+    drop(node);
+    assert_eq!(tree.free_list_len(), 0);
+    drop(clone);
+    assert_eq!(tree.free_list_len(), 1);
+    for _ in 0..3 {
+        drop(tree.root().ensure_child(Value(1)));
+    }
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(tree.gc().processed, 1);
+}
+
+#[test]
+fn reused_nodes_survive_generations() {
+    let tree = builder().gc_generations(2).build(Value(0));
+    // Releasing a node waiting on the free list after looking it up again
+    // resets its age.
+    for _ in 0..5 {
+        drop(tree.root().ensure_child(Value(1)));
+        let report = tree.gc();
+        assert_eq!(report.requeued, 1);
+        assert_eq!(report.freed, 0);
+    }
+    assert_eq!(live_nodes(), 2);
+
+    // Traversing the tree doesn't count as using the node.
+    assert_eq!(tree.gc().requeued, 1);
+    assert_eq!(tree.root().children().len(), 1);
+    assert_eq!(tree.gc().freed, 1);
+    assert_eq!(live_nodes(), 1);
+}
+
+#[test]
+fn maybe_gc_follows_the_free_list_length() {
+    let tree = builder().gc_policy(FixedCount(10)).build(Value(0));
+    for i in 0..10 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    assert!(tree.maybe_gc().is_none());
+
+    drop(tree.root().ensure_child(Value(10)));
+    let report = tree.maybe_gc().unwrap();
+    assert_eq!(report.freed, 11);
+    assert_eq!(tree.free_list_len(), 0);
+
+    // The free list length went back down, so the threshold must be crossed
+    // again before the next collection.
+    drop(tree.root().ensure_child(Value(11)));
+    assert_eq!(tree.free_list_len(), 1);
+    assert!(tree.maybe_gc().is_none());
+}
+
+#[test]
+fn dropping_the_tree_frees_unused_nodes() {
+    let tree = tree();
+    for i in 1..=5 {
+        drop(tree.root().ensure_path((i..=i + 2).map(Value)));
+    }
+    let held = tree.root().ensure_child(Value(42));
+    drop(tree);
+    // Only the held node and the root it keeps alive remain.
+    assert_eq!(live_nodes(), 2);
+    assert_eq!(held.value().0, 42);
+    drop(held);
+    assert_eq!(live_nodes(), 0);
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{builder, live_nodes, tree, Value};
+use recycling_tree::TryEnsureChildError;
+
+#[test]
+fn try_ensure_child_with_registers_keys() {
+    let tree = builder().key_index(true).build(Value(0));
+    let a = tree.root().try_ensure_child_with(1, || Ok::<_, ()>(Value(1))).unwrap();
+    let b = a.try_ensure_child_with(1, || Ok::<_, ()>(Value(1))).unwrap();
+    let c = tree.root().ensure_path([2, 1].iter().map(|&key| Value(key)));
+    assert_eq!(live_nodes(), 5);
+
+    assert_eq!(tree.invalidate(&1), 3);
+    assert!(tree.root().get_child(&1).is_none());
+    assert_eq!(tree.invalidate(&1), 0);
+    drop((a, b, c));
+    tree.gc();
+    assert_eq!(live_nodes(), 1);
+}
+
+#[test]
+fn try_ensure_child_with_errors_leave_the_index_alone() {
+    let tree = builder().key_index(true).build(Value(0));
+    let result = tree.root().try_ensure_child_with(1, || Err("nope"));
+    assert!(matches!(result, Err(TryEnsureChildError::Value("nope"))));
+    assert_eq!(tree.invalidate(&1), 0);
+
+    let node = tree.root().try_ensure_child_with(1, || Ok::<_, ()>(Value(1))).unwrap();
+    assert_eq!(tree.invalidate(&1), 1);
+    drop(node);
+    drop(tree);
+    assert_eq!(live_nodes(), 0);
+}
+
+#[test]
+fn invalidate_walks_the_tree_without_index() {
+    let tree = tree();
+    let held = tree.root().ensure_path([3, 1, 2].iter().map(|&key| Value(key)));
+    drop(tree.root().ensure_path([1, 2].iter().map(|&key| Value(key))));
+    drop(tree.root().ensure_child(Value(2)));
+    drop(tree.root().ensure_child(Value(4)));
+    assert_eq!(tree.free_list_len(), 3);
+    assert_eq!(live_nodes(), 8);
+
+    assert_eq!(tree.invalidate(&2), 3);
+    assert!(tree.root().get_child(&2).is_none());
+    assert!(tree.find_path([1, 2]).is_none());
+    assert!(tree.find_path([3, 1, 2]).is_none());
+    assert_eq!(tree.invalidate(&2), 0);
+    // The detached nodes stay valid, and new ones are created in their
+    // place.
+    assert_eq!(held.value().0, 2);
+    let new = tree.find_path([3, 1]).unwrap().ensure_child(Value(2));
+    assert_eq!(live_nodes(), 9);
+
+    // The unused nodes, detached or not, are freed by the next collection,
+    // along with their parents which aren't used anymore.
+    assert_eq!(tree.gc().freed, 4);
+    assert_eq!(live_nodes(), 5);
+    drop((held, new));
+    tree.gc();
+    assert_eq!(live_nodes(), 1);
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{live_nodes, tree, Value};
+
+#[test]
+fn borrowed_children_are_not_resurrected() {
+    let tree = tree();
+    drop(tree.root().ensure_path((1..=3).map(Value)));
+    assert_eq!(tree.free_list_len(), 1);
+
+    let root = tree.root_ref();
+    let found = root.with_child(&1, |child| {
+        child.with_child(&2, |grandchild| {
+            assert!(grandchild.with_child(&4, |_| ()).is_none());
+            // Looking up the same children again doesn't deadlock.
+            assert_eq!(root.with_child(&1, |child| child.value().0), Some(1));
+            grandchild.with_child(&3, |leaf| {
+                assert_eq!(leaf.parent().unwrap().value().0, 2);
+                leaf.value().0
+            })
+        })
+    });
+    assert_eq!(found, Some(Some(Some(3))));
+
+    // Borrowing the leaf didn't resurrect it, so it is freed along with its
+    // ancestors.
+    assert_eq!(tree.gc().freed, 3);
+    assert_eq!(live_nodes(), 1);
+}
+
+#[test]
+fn borrowed_children_can_be_upgraded() {
+    let tree = tree();
+    drop(tree.root().ensure_child(Value(1)));
+
+    let node = tree.root_ref().with_child(&1, |child| child.to_node()).unwrap();
+    let report = tree.gc();
+    assert_eq!(report.resurrected, 1);
+    assert_eq!(report.freed, 0);
+    assert_eq!(node.value().0, 1);
+    drop(node);
+    assert_eq!(tree.gc().freed, 1);
+}
+
+#[test]
+fn children_are_unlocked_after_borrowing() {
+    let tree = tree();
+    drop(tree.root().ensure_child(Value(1)));
+    drop(tree.root().ensure_child(Value(2)));
+
+    assert_eq!(tree.root_ref().with_child(&1, |child| child.value().0), Some(1));
+    // Creating and freeing children of the same node doesn't deadlock.
+    let node = tree.root().ensure_child(Value(3));
+    assert_eq!(tree.gc().freed, 2);
+    assert_eq!(tree.root_ref().with_child(&3, |child| child.value().0), Some(3));
+    assert!(tree.root_ref().with_child(&1, |_| ()).is_none());
+    drop(node);
+    assert_eq!(tree.gc().freed, 1);
+    assert_eq!(live_nodes(), 1);
+}
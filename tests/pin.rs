@@ -0,0 +1,95 @@
+mod common;
+
+use common::{builder, dropped_values, live_nodes, tree, Value};
+
+#[test]
+fn pinning_a_node_pins_its_ancestors() {
+    let tree = tree();
+    let leaf = tree.root().ensure_path((1..=3).map(Value));
+    let sibling = tree.root().ensure_child(Value(4));
+    assert!(!tree.has_pinned());
+
+    leaf.pin();
+    assert!(tree.has_pinned());
+    assert!(leaf.ancestors().all(|node| node.is_pinned()));
+    assert!(!sibling.is_pinned());
+    drop(sibling);
+    drop(tree);
+    // The pinned nodes are leaked, along with the root.
+    assert_eq!(dropped_values(), [4]);
+    assert_eq!(live_nodes(), 4);
+}
+
+#[test]
+fn references_to_pinned_nodes_are_never_released() {
+    let tree = tree();
+    let before = tree.root().ensure_child(Value(1));
+    let clone = before.clone();
+    before.pin();
+    let after = tree.root().get_child(&1).unwrap();
+    let after_clone = after.clone();
+    drop(before);
+    drop(after);
+    assert_eq!(tree.free_list_len(), 0);
+    drop((clone, after_clone));
+    assert_eq!(tree.free_list_len(), 0);
+
+    assert_eq!(tree.gc().processed, 0);
+    assert_eq!(tree.root().get_child(&1).unwrap().value().0, 1);
+    assert_eq!(live_nodes(), 2);
+}
+
+#[test]
+fn pinned_nodes_waiting_on_the_free_list_are_not_freed() {
+    let tree = tree();
+    drop(tree.root().ensure_child(Value(1)));
+    assert_eq!(tree.free_list_len(), 1);
+
+    let node = tree.root().get_child(&1).unwrap();
+    node.pin();
+    drop(node);
+    let report = tree.gc();
+    assert_eq!(report.processed, 1);
+    assert_eq!(report.freed, 0);
+    assert_eq!(report.resurrected, 1);
+    assert_eq!(tree.free_list_len(), 0);
+
+    // The node doesn't go back on the free list.
+    drop(tree.root().get_child(&1).unwrap());
+    assert_eq!(tree.free_list_len(), 0);
+    assert_eq!(tree.gc().processed, 0);
+    assert_eq!(dropped_values(), []);
+    assert_eq!(live_nodes(), 2);
+}
+
+#[test]
+fn children_of_pinned_nodes_are_freed() {
+    let tree = tree();
+    let parent = tree.root().ensure_child(Value(1));
+    parent.pin();
+    drop(parent.ensure_child(Value(2)));
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(tree.gc().freed, 1);
+    assert_eq!(dropped_values(), [2]);
+    assert!(!parent.has_children());
+    assert_eq!(live_nodes(), 2);
+}
+
+#[test]
+fn children_of_pinned_nodes_are_evicted() {
+    let tree = builder().free_list_capacity(1).build(Value(0));
+    let parent = tree.root().ensure_child(Value(1));
+    parent.pin();
+    drop(parent.ensure_child(Value(2)));
+    drop(parent.ensure_child(Value(3)));
+    assert_eq!(dropped_values(), [2]);
+    assert_eq!(tree.free_list_len(), 1);
+
+    // Evicting the last child doesn't push the pinned parent on the free
+    // list.
+    drop(tree.root().ensure_child(Value(4)));
+    assert_eq!(dropped_values(), [3]);
+    assert_eq!(tree.free_list_len(), 1);
+    assert!(!parent.has_children());
+    assert_eq!(live_nodes(), 3);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{builder, Value};
+use recycling_tree::{ByteBudget, FreeRatio, GcPolicy, GcStats, Never};
+
+#[test]
+fn free_ratio_compares_free_and_live_nodes() {
+    let tree = builder().gc_policy(FreeRatio(0.5)).build(Value(0));
+    let live = (1..=4).map(|i| tree.root().ensure_child(Value(i))).collect::<Vec<_>>();
+    drop(tree.root().ensure_child(Value(5)));
+    drop(tree.root().ensure_child(Value(6)));
+    assert_eq!(tree.gc_stats().live(), 4);
+    assert!(tree.maybe_gc().is_none());
+
+    drop(tree.root().ensure_child(Value(7)));
+    assert_eq!(tree.maybe_gc().unwrap().freed, 3);
+    assert_eq!(tree.free_list_len(), 0);
+    drop(live);
+}
+
+#[test]
+fn free_ratio_collects_without_live_nodes() {
+    let tree = builder().gc_policy(FreeRatio(1.0)).build(Value(0));
+    assert!(tree.maybe_gc().is_none());
+    drop(tree.root().ensure_child(Value(1)));
+    assert_eq!(tree.gc_stats().live(), 0);
+    assert_eq!(tree.maybe_gc().unwrap().freed, 1);
+
+    let stats = GcStats { free: 0, allocated: 0, node_size: 64 };
+    assert!(!FreeRatio(0.0).should_collect(&stats));
+    let stats = GcStats { free: 1, allocated: 1, node_size: 64 };
+    assert!(FreeRatio(0.0).should_collect(&stats));
+}
+
+#[test]
+fn byte_budget_counts_the_size_of_free_nodes() {
+    let node_size = builder().build(Value(0)).gc_stats().node_size;
+    assert!(node_size > 0);
+    let tree = builder().gc_policy(ByteBudget(2 * node_size)).build(Value(0));
+    drop(tree.root().ensure_child(Value(1)));
+    drop(tree.root().ensure_child(Value(2)));
+    assert!(tree.maybe_gc().is_none());
+    drop(tree.root().ensure_child(Value(3)));
+    assert_eq!(tree.maybe_gc().unwrap().freed, 3);
+
+    let stats = GcStats { free: usize::MAX, allocated: usize::MAX, node_size };
+    assert!(!ByteBudget(usize::MAX).should_collect(&stats));
+}
+
+#[test]
+fn never_leaves_collections_to_the_caller() {
+    let tree = builder().gc_policy(Never).build(Value(0));
+    for i in 1..=100 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    assert!(tree.maybe_gc().is_none());
+    assert_eq!(tree.free_list_len(), 100);
+    assert_eq!(tree.gc().freed, 100);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{is_live_node, live_nodes, tree, LocalLogger, Value};
+use recycling_tree::Node;
+
+type TestNode = Node<u32, Value, LocalLogger>;
+
+#[test]
+fn raw_pointers_round_trip() {
+    let tree = tree();
+    let node = tree.root().ensure_child(Value(1));
+    let ptr = node.as_ptr();
+    let raw = node.clone().into_raw();
+    assert_eq!(raw, ptr);
+
+    // The raw pointer holds a reference to the node.
+    drop(node);
+    assert_eq!(tree.free_list_len(), 0);
+    let value = unsafe { TestNode::with_raw(raw, |node| node.value().0) };
+    assert_eq!(value, 1);
+    assert_eq!(tree.free_list_len(), 0);
+
+    let node = unsafe { TestNode::from_raw(raw) };
+    assert_eq!(node.as_ptr(), ptr);
+    assert_eq!(node.value().0, 1);
+    drop(node);
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(tree.gc().freed, 1);
+    assert_eq!(live_nodes(), 1);
+}
+
+#[test]
+fn raw_pointers_balance_refcounts() {
+    let tree = tree();
+    let node = tree.root().ensure_child(Value(1));
+    let raws = (0..10).map(|_| node.clone().into_raw()).collect::<Vec<_>>();
+    drop(node);
+    for &raw in &raws {
+        // Cloning the borrowed node creates a new reference.
+        let clone = unsafe { TestNode::with_raw(raw, TestNode::clone) };
+        drop(clone);
+    }
+    for raw in raws {
+        assert_eq!(tree.free_list_len(), 0);
+        drop(unsafe { TestNode::from_raw(raw) });
+    }
+    // The node is pushed on the free list exactly once.
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(tree.gc().freed, 1);
+    assert_eq!(live_nodes(), 1);
+}
+
+#[test]
+fn pointers_match_the_logger() {
+    let tree = tree();
+    assert!(is_live_node(tree.root().as_ptr()));
+    let one = tree.root().ensure_child(Value(1));
+    let two = tree.root().ensure_child(Value(2));
+    assert!(is_live_node(one.as_ptr()));
+    assert!(is_live_node(two.as_ptr()));
+    assert_ne!(one.as_ptr(), two.as_ptr());
+    assert_eq!(one.clone().as_ptr(), one.as_ptr());
+    assert_eq!(tree.root().get_child(&1).unwrap().as_ptr(), one.as_ptr());
+    assert_eq!(two.parent().unwrap().as_ptr(), tree.root().as_ptr());
+
+    let ptr = one.as_ptr();
+    drop(one);
+    assert!(is_live_node(ptr));
+    tree.gc();
+    assert!(!is_live_node(ptr));
+    assert_eq!(live_nodes(), 2);
+}
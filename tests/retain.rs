@@ -0,0 +1,102 @@
+mod common;
+
+use common::{builder, dropped_values, live_nodes, tree, TestTree, Value};
+use std::panic::{self, AssertUnwindSafe};
+
+#[test]
+fn retained_nodes_stay_queued() {
+    let tree = tree();
+    for i in 1..=4 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    let report = tree.retain(|value| value.0 % 2 == 0);
+    assert_eq!(report.processed, 4);
+    assert_eq!(report.freed, 2);
+    assert_eq!(report.requeued, 2);
+    assert_eq!(dropped_values(), [1, 3]);
+    assert_eq!(tree.free_list_len(), 2);
+    assert_eq!(tree.root().child_count(), 2);
+
+    assert_eq!(tree.gc().freed, 2);
+    assert_eq!(dropped_values(), [2, 4]);
+    assert_eq!(live_nodes(), 1);
+}
+
+#[test]
+fn retain_never_frees_resurrected_nodes() {
+    let tree = tree();
+    drop(tree.root().ensure_child(Value(1)));
+    let node = tree.root().get_child(&1).unwrap();
+    let report = tree.retain(|_| false);
+    assert_eq!(report.processed, 1);
+    assert_eq!(report.freed, 0);
+    assert_eq!(report.resurrected, 1);
+    assert_eq!(tree.free_list_len(), 0);
+    assert_eq!(dropped_values(), []);
+
+    // The node goes back on the free list once unused again.
+    assert_eq!(node.value().0, 1);
+    drop(node);
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(tree.retain(|_| false).freed, 1);
+}
+
+#[test]
+fn retain_frees_parents_in_cascade() {
+    let tree = tree();
+    drop(tree.root().ensure_path((1..=3).map(Value)));
+    let mut seen = vec![];
+    let report = tree.retain(|value| {
+        seen.push(value.0);
+        false
+    });
+    // Only the leaf was waiting on the free list.
+    assert_eq!(seen, [3]);
+    assert_eq!(report.processed, 1);
+    assert_eq!(report.freed, 3);
+    assert_eq!(dropped_values(), [1, 2, 3]);
+    assert_eq!(live_nodes(), 1);
+}
+
+fn panic_in_retain(tree: &TestTree) {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        tree.retain(|value| match value.0 {
+            2 => panic!("retain"),
+            i => i == 3,
+        })
+    }));
+    assert!(result.is_err());
+}
+
+#[test]
+fn panics_in_retain_requeue_the_rest_of_the_free_list() {
+    let tree = tree();
+    for i in 1..=4 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    // The most recently released nodes are processed first.
+    panic_in_retain(&tree);
+    assert_eq!(dropped_values(), [4]);
+    assert_eq!(tree.free_list_len(), 3);
+    assert_eq!(tree.gc().freed, 3);
+    assert_eq!(live_nodes(), 1);
+}
+
+#[test]
+fn panics_in_retain_keep_the_free_list_bounded() {
+    let tree = builder().free_list_capacity(3).build(Value(0));
+    for i in 1..=4 {
+        drop(tree.root().ensure_child(Value(i)));
+    }
+    assert_eq!(dropped_values(), [1]);
+    panic_in_retain(&tree);
+    assert_eq!(dropped_values(), [4]);
+    assert_eq!(tree.free_list_len(), 2);
+
+    // The requeued nodes are evicted in the order they were released in.
+    drop(tree.root().ensure_child(Value(5)));
+    drop(tree.root().ensure_child(Value(6)));
+    assert_eq!(dropped_values(), [2]);
+    drop(tree.root().ensure_child(Value(7)));
+    assert_eq!(dropped_values(), [3]);
+}
@@ -0,0 +1,107 @@
+//! Creates, resurrects, drops and collects nodes from many threads at once.
+
+mod common;
+
+use common::{GlobalLogger, Rng, Value};
+use recycling_tree::{Node, Tree};
+use std::sync::Arc;
+use std::thread;
+
+const THREADS: u64 = 8;
+const ITERATIONS: usize = 5_000;
+
+type StressTree = Tree<u32, Value, GlobalLogger>;
+
+fn random_node(tree: &StressTree, rng: &mut Rng) -> Node<u32, Value, GlobalLogger> {
+    let keys = (0..rng.below(5)).map(|_| rng.below(4) + 1).collect::<Vec<_>>();
+    match rng.below(4) {
+        0 => tree.root().ensure_path(keys.iter().map(|&key| Value(key))),
+        1 => {
+            let mut node = tree.root().clone();
+            for &key in &keys {
+                node = node.ensure_child(Value(key));
+            }
+            node
+        }
+        2 => {
+            let mut node = tree.root().clone();
+            for &key in &keys {
+                match node.get_child(&key) {
+                    Some(child) => node = child,
+                    None => break,
+                }
+            }
+            node
+        }
+        _ => tree.longest_prefix(&keys).0,
+    }
+}
+
+fn stress(tree: StressTree) {
+    let _serial = common::serialize();
+    let tree = Arc::new(tree);
+    let threads = (0..THREADS)
+        .map(|seed| {
+            let tree = tree.clone();
+            thread::spawn(move || {
+                let mut rng = Rng::new(seed);
+                let mut held = vec![];
+                let mut weak = vec![];
+                for _ in 0..ITERATIONS {
+                    let node = random_node(&tree, &mut rng);
+                    let depth = node.ancestor_values().count();
+                    assert_eq!(node.path_from_root().len(), depth);
+                    match rng.below(3) {
+                        0 => held.push(node),
+                        1 => weak.push((node.downgrade(), node.value().0, depth)),
+                        _ => {}
+                    }
+                    if held.len() > 16 {
+                        let i = rng.below(held.len() as u32) as usize;
+                        held.swap_remove(i);
+                    }
+                    // Weak references are upgraded while other threads may be
+                    // collecting or evicting their nodes.
+                    if weak.len() > 16 {
+                        let i = rng.below(weak.len() as u32) as usize;
+                        let (weak, key, depth) = weak.swap_remove(i);
+                        if let Some(node) = weak.upgrade() {
+                            assert_eq!(node.value().0, key);
+                            assert_eq!(node.ancestor_values().count(), depth);
+                        }
+                    }
+                    match rng.below(50) {
+                        0 => drop(tree.gc()),
+                        1 => drop(tree.maybe_gc()),
+                        2 => drop(tree.gc_step(8)),
+                        3 => drop(tree.retain(|value| value.0 % 2 == 0)),
+                        4 => drop(tree.invalidate(&(rng.below(4) + 1))),
+                        _ => {}
+                    }
+                }
+                held
+            })
+        })
+        .collect::<Vec<_>>();
+    let held = threads.into_iter().map(|thread| thread.join().unwrap()).collect::<Vec<_>>();
+
+    assert_eq!(tree.gc_stats().allocated + 1, common::global_live_nodes());
+    drop(held);
+    while tree.free_list_len() != 0 {
+        tree.gc();
+    }
+    assert_eq!(common::global_live_nodes(), 1);
+
+    drop(tree);
+    assert_eq!(common::global_live_nodes(), 0);
+}
+
+#[test]
+fn stress_unbounded() {
+    stress(StressTree::with_logger(Value(0)));
+}
+
+#[test]
+fn stress_bounded() {
+    stress(StressTree::builder().free_list_capacity(16).gc_generations(1).build(Value(0)));
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::{path, tree, LocalLogger, TestTree, Value};
+use recycling_tree::{Node, Traversal, Visitor};
+
+type TestNode = Node<u32, Value, LocalLogger>;
+
+/// Builds the following tree, returning it with its leaves:
+///
+/// ```text
+/// 0
+/// ├── 1
+/// │   ├── 3
+/// │   │   └── 5
+/// │   └── 4
+/// └── 2
+///     └── 6
+/// ```
+fn fixed_tree() -> (TestTree, Vec<TestNode>) {
+    let tree = tree();
+    let leaves = [&[1, 3, 5][..], &[1, 4], &[2, 6]]
+        .iter()
+        .map(|keys| tree.root().ensure_path(keys.iter().map(|&key| Value(key))))
+        .collect();
+    (tree, leaves)
+}
+
+fn sorted(mut paths: Vec<Vec<u32>>) -> Vec<Vec<u32>> {
+    paths.sort();
+    paths
+}
+
+fn all_paths() -> Vec<Vec<u32>> {
+    sorted(vec![vec![1], vec![1, 3], vec![1, 3, 5], vec![1, 4], vec![2], vec![2, 6]])
+}
+
+#[test]
+fn depth_first_visits_whole_subtrees_in_turn() {
+    let (tree, _leaves) = fixed_tree();
+    let paths = tree.root().descendants(Traversal::DepthFirst).map(|node| path(&node));
+    let paths = paths.collect::<Vec<_>>();
+    assert_eq!(sorted(paths.clone()), all_paths());
+    for (i, node) in paths.iter().enumerate() {
+        // Each node is directly followed by all of its descendants.
+        let descendants = paths.iter().filter(|other| other.len() > node.len());
+        let count = descendants.filter(|other| other.starts_with(node)).count();
+        assert!(paths[i + 1..=i + count].iter().all(|other| other.starts_with(node)));
+    }
+
+    let subtree = tree.find_path([1]).unwrap().descendants(Traversal::DepthFirst);
+    let subtree = subtree.map(|node| path(&node)).collect::<Vec<_>>();
+    assert_eq!(subtree.len(), 3);
+    let position = |target: &[u32]| subtree.iter().position(|path| path == target).unwrap();
+    assert_eq!(position(&[1, 3, 5]), position(&[1, 3]) + 1);
+}
+
+#[test]
+fn breadth_first_visits_levels_in_turn() {
+    let (tree, _leaves) = fixed_tree();
+    let paths = tree.root().descendants(Traversal::BreadthFirst).map(|node| path(&node));
+    let paths = paths.collect::<Vec<_>>();
+    assert_eq!(sorted(paths.clone()), all_paths());
+    let depths = paths.iter().map(Vec::len).collect::<Vec<_>>();
+    assert_eq!(depths, [1, 1, 2, 2, 2, 3]);
+}
+
+#[test]
+fn descendants_only_lock_children_while_snapshotting_them() {
+    let (tree, _leaves) = fixed_tree();
+    let mut descendants = tree.root().descendants(Traversal::DepthFirst);
+    let first = descendants.next().unwrap();
+    let other = tree.root().get_child(&(3 - first.value().0)).unwrap();
+    // The children of the root and of the first node were snapshotted
+    // already, but not the ones of its sibling.
+    drop(tree.root().ensure_child(Value(7)));
+    drop(first.ensure_child(Value(8)));
+    drop(other.ensure_child(Value(9)));
+    let paths = descendants.map(|node| path(&node)).collect::<Vec<_>>();
+    assert!(paths.contains(&vec![other.value().0, 9]));
+    assert!(!paths.iter().any(|path| path.contains(&7) || path.contains(&8)));
+    assert_eq!(paths.len(), 6);
+}
+
+#[derive(Default)]
+struct Recorder {
+    events: Vec<(bool, Vec<u32>)>,
+}
+
+impl Visitor<u32, Value, LocalLogger> for Recorder {
+    fn enter(&mut self, node: &TestNode) {
+        self.events.push((true, path(node)));
+        if path(node) == [2, 6] {
+            // No lock is held while visiting a node, and its children are
+            // only snapshotted afterwards.
+            drop(node.ensure_child(Value(7)));
+            drop(node.parent().unwrap().ensure_child(Value(8)));
+        }
+    }
+
+    fn leave(&mut self, node: &TestNode) {
+        self.events.push((false, path(node)));
+    }
+}
+
+#[test]
+fn walk_pairs_enter_and_leave() {
+    let (tree, _leaves) = fixed_tree();
+    let mut recorder = Recorder::default();
+    tree.root().walk(&mut recorder);
+
+    let events = recorder.events;
+    assert_eq!(events.first(), Some(&(true, vec![])));
+    assert_eq!(events.last(), Some(&(false, vec![])));
+    let mut stack = Vec::<Vec<u32>>::new();
+    let mut entered = vec![];
+    for (enter, path) in events {
+        if enter {
+            if let Some(parent) = stack.last() {
+                assert_eq!(path[..path.len() - 1], parent[..]);
+            }
+            stack.push(path.clone());
+            entered.push(path);
+        } else {
+            assert_eq!(stack.pop(), Some(path));
+        }
+    }
+    assert!(stack.is_empty());
+
+    let mut expected = all_paths();
+    expected.push(vec![]);
+    expected.push(vec![2, 6, 7]);
+    assert_eq!(sorted(entered), sorted(expected));
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{builder, dropped_values, live_nodes, tree, Value};
+
+#[test]
+fn upgrade_resurrects_nodes_waiting_on_the_free_list() {
+    let tree = tree();
+    let node = tree.root().ensure_child(Value(1));
+    let weak = node.downgrade();
+    assert_eq!(weak.upgrade().unwrap().value().0, 1);
+    drop(node);
+    assert_eq!(tree.free_list_len(), 1);
+
+    let node = weak.upgrade().unwrap();
+    let report = tree.gc();
+    assert_eq!(report.freed, 0);
+    assert_eq!(report.resurrected, 1);
+    assert_eq!(node.value().0, 1);
+
+    // The upgraded reference goes back on the free list once dropped.
+    drop(node);
+    assert_eq!(tree.free_list_len(), 1);
+    assert_eq!(tree.gc().freed, 1);
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn upgrade_fails_after_gc() {
+    let tree = tree();
+    let leaf = tree.root().ensure_path((1..=2).map(Value));
+    let weak_leaf = leaf.downgrade();
+    let weak_parent = leaf.parent().unwrap().downgrade();
+    let clone = weak_leaf.clone();
+    drop(leaf);
+    assert_eq!(tree.gc().freed, 2);
+    assert!(weak_leaf.upgrade().is_none());
+    assert!(weak_parent.upgrade().is_none());
+    assert!(clone.upgrade().is_none());
+
+    // A new node with the same key doesn't revive the weak references.
+    let new = tree.root().ensure_child(Value(1));
+    assert!(weak_parent.upgrade().is_none());
+    drop(new);
+    assert_eq!(live_nodes(), 2);
+}
+
+#[test]
+fn upgrade_fails_after_eviction() {
+    let tree = builder().free_list_capacity(1).build(Value(0));
+    let weak = tree.root().ensure_child(Value(1)).downgrade();
+    assert!(weak.upgrade().is_some());
+    drop(tree.root().ensure_child(Value(2)));
+    assert_eq!(dropped_values(), [1]);
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn upgrade_fails_after_dropping_the_tree() {
+    let tree = tree();
+    let weak_child = tree.root().ensure_child(Value(1)).downgrade();
+    let weak_root = tree.root().downgrade();
+    let held = tree.root().ensure_child(Value(2));
+    let weak_held = held.downgrade();
+    drop(tree);
+    assert!(weak_child.upgrade().is_none());
+    // The held node keeps the root alive.
+    assert_eq!(weak_held.upgrade().unwrap().value().0, 2);
+    assert_eq!(weak_root.upgrade().unwrap().value().0, 0);
+    drop(held);
+    assert!(weak_held.upgrade().is_none());
+    assert!(weak_root.upgrade().is_none());
+    assert_eq!(live_nodes(), 0);
+}